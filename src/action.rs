@@ -1,10 +1,13 @@
-use crate::{room::RoomId, span::Spanned};
+use crate::{room::RoomId, span::Spanned, variable::Value};
 use color_eyre::eyre::bail;
 use color_eyre::Report;
 use std::fmt::Display;
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
+// Actions only live in the parsed story, where their size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Action {
     Goto(Spanned<RoomId>),
     Modify {
@@ -13,17 +16,97 @@ pub enum Action {
         what: Spanned<String>,
         room: Spanned<RoomId>,
     },
+    /// Changes a story-global variable.
+    Set {
+        operator: Spanned<Operator>,
+        value: Spanned<Value>,
+        variable: Spanned<String>,
+    },
+    /// Goes to one of the rooms at random, `70% hit | 30% miss`.
+    /// The chances add up to 100.
+    Branch(Vec<Branch>),
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Branch {
+    /// In percent.
+    pub chance: Spanned<u8>,
+    pub room: Spanned<RoomId>,
+}
+
+impl Action {
+    /// Whether the action decides where the player goes next,
+    /// as opposed to changing items or variables.
+    pub fn moves(&self) -> bool {
+        matches!(self, Action::Goto(_) | Action::Branch(_))
+    }
+
+    /// The rooms the action may lead to.
+    pub fn targets(&self) -> impl Iterator<Item = &Spanned<RoomId>> {
+        let (goto, branches) = match self {
+            Action::Goto(target) => (Some(target), &[][..]),
+            Action::Branch(branches) => (None, &branches[..]),
+            Action::Modify { .. } | Action::Set { .. } => (None, &[][..]),
+        };
+        goto.into_iter()
+            .chain(branches.iter().map(|branch| &branch.room))
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operator {
     Add,
+    Sub,
+    Mul,
+    Div,
+    Assign,
+}
+
+impl Operator {
+    /// Computes the new value of an item.
+    /// Returns `None` on overflow, underflow or division by zero.
+    pub fn apply(&self, value: usize, amount: usize) -> Option<usize> {
+        match self {
+            Operator::Add => value.checked_add(amount),
+            Operator::Sub => value.checked_sub(amount),
+            Operator::Mul => value.checked_mul(amount),
+            Operator::Div => value.checked_div(amount),
+            Operator::Assign => Some(amount),
+        }
+    }
+
+    /// Why [`Operator::apply`] returned `None` for `amount`.
+    pub fn failure(&self, amount: usize) -> &'static str {
+        match self {
+            Operator::Div if amount == 0 => "division by zero",
+            Operator::Sub => "this would underflow",
+            _ => "this would overflow",
+        }
+    }
+
+    /// Whether the operator can change a variable holding `value` by `amount`.
+    /// Explains why not otherwise.
+    pub fn check(&self, value: Value, amount: Value) -> Result<(), String> {
+        if !value.same_type(&amount) {
+            return Err(format!("expected {}", value.type_description()));
+        }
+        if matches!(value, Value::Bool(_)) && !matches!(self, Operator::Assign) {
+            return Err("booleans can only be assigned with `=`".into());
+        }
+        Ok(())
+    }
 }
 
 impl Display for Operator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
             Operator::Add => "+=",
+            Operator::Sub => "-=",
+            Operator::Mul => "*=",
+            Operator::Div => "/=",
+            Operator::Assign => "=",
         })
     }
 }
@@ -34,6 +117,10 @@ impl FromStr for Operator {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s {
             "+=" => Operator::Add,
+            "-=" => Operator::Sub,
+            "*=" => Operator::Mul,
+            "/=" => Operator::Div,
+            "=" => Operator::Assign,
             _ => bail!("unknown operator `{s}`"),
         })
     }
@@ -58,6 +145,24 @@ impl Display for Action {
                     amount.content
                 )
             }
+            Action::Set {
+                operator,
+                value,
+                variable,
+            } => write!(
+                f,
+                "{} {} {}",
+                variable.content, operator.content, value.content
+            ),
+            Action::Branch(branches) => {
+                for (i, branch) in branches.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}% {}", branch.chance.content, branch.room.content.id())?;
+                }
+                Ok(())
+            }
         }
     }
 }
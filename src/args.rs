@@ -0,0 +1,40 @@
+use color_eyre::{eyre::eyre, Report, Result};
+
+/// The command line arguments of a binary after the story file. ui_test passes
+/// `--cfg=<revision>` for revisions, which is skipped. Binaries should reject all other
+/// arguments they don't know with [`unknown`], so typos don't go unnoticed.
+pub struct Args<I> {
+    args: I,
+}
+
+impl Args<std::iter::Skip<std::env::Args>> {
+    pub fn from_env() -> Self {
+        Self::new(std::env::args().skip(2))
+    }
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    pub fn new(args: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            args: args.into_iter(),
+        }
+    }
+
+    /// The argument after `flag`, described as `what` if it is missing.
+    pub fn value(&mut self, flag: &str, what: &str) -> Result<String> {
+        self.next()
+            .ok_or_else(|| eyre!("{flag} must be followed by {what}"))
+    }
+}
+
+impl<I: Iterator<Item = String>> Iterator for Args<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.args.find(|arg| !arg.starts_with("--cfg="))
+    }
+}
+
+pub fn unknown(arg: &str) -> Report {
+    eyre!("unknown argument `{arg}`")
+}
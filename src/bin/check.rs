@@ -0,0 +1,32 @@
+use color_eyre::{
+    eyre::{ensure, eyre},
+    Result,
+};
+use story_rebase::{
+    args::{unknown, Args},
+    diagnostic::Severity,
+    parsing::parse,
+    span::Spanned,
+    validate::validate,
+};
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("first argument must be a filename to process"))?;
+    if let Some(arg) = Args::from_env().next() {
+        return Err(unknown(&arg));
+    }
+    let story = Spanned::read_from_file(path)?;
+    let story = parse(story.as_ref())?;
+    let problems = validate(&story);
+    for problem in &problems {
+        println!("{}\n", problem.render());
+    }
+    let errors = problems
+        .iter()
+        .filter(|problem| problem.severity == Severity::Error)
+        .count();
+    ensure!(errors == 0, "found {errors} errors");
+    Ok(())
+}
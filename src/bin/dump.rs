@@ -1,10 +1,43 @@
-use color_eyre::Result;
-use story_rebase::{parsing::parse, span::Spanned};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use story_rebase::{
+    args::{unknown, Args},
+    diagnostic::Diagnostics,
+    parsing::parse_recovering,
+    span::Spanned,
+};
 
 fn main() -> Result<()> {
     let path = std::env::args().nth(1).unwrap();
+    let mut args = Args::from_env();
+    let mut json = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().as_deref() {
+                Some("debug") => json = false,
+                Some("json") => json = true,
+                other => bail!("unknown format {other:?}, expected `debug` or `json`"),
+            },
+            other => return Err(unknown(other)),
+        }
+    }
     let story = Spanned::read_from_file(path)?;
-    let story = parse(story.as_ref())?;
-    println!("{story:#?}");
-    Ok(())
+    let (story, errors) = parse_recovering(story.as_ref());
+    if let Some(story) = story {
+        if json {
+            #[cfg(feature = "serde")]
+            println!("{}", serde_json::to_string_pretty(&story)?);
+            #[cfg(not(feature = "serde"))]
+            bail!("`--format json` requires building with the `serde` feature");
+        } else {
+            println!("{story:#?}");
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(Diagnostics(errors)))
+    }
 }
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use color_eyre::{
+    eyre::{ensure, eyre, Context},
+    Result,
+};
+use story_rebase::{
+    args::{unknown, Args},
+    explore::explore,
+    parsing::parse,
+    regression::{add_revisions, has_revisions, scenarios, ORIGINAL_REVISION},
+    span::Spanned,
+};
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("first argument must be a filename to process"))?;
+    let mut args = Args::from_env();
+    let mut limit = 10_000;
+    let mut write_stdin = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => {
+                limit = args.value("--limit", "a number of states")?.parse()?;
+            }
+            "--write-stdin" => write_stdin = true,
+            other => return Err(unknown(other)),
+        }
+    }
+    let source = Spanned::read_from_file(&path)?;
+    let story = parse(source.as_ref())?;
+    let exploration = explore(&story, limit);
+    print!("{exploration}");
+    if write_stdin {
+        // `step` ui tests, one revision per ending
+        let (scenarios, errors) = scenarios(&story, &exploration);
+        for error in errors {
+            eprintln!("skipped: {error}");
+        }
+        let path = Path::new(&path);
+        let stem = path
+            .file_stem()
+            .ok_or_else(|| eyre!("{} is not a file", path.display()))?;
+        let file = |suffix: &str| {
+            let mut name = stem.to_owned();
+            name.push(suffix);
+            path.with_file_name(name)
+        };
+        if !scenarios.is_empty() && !has_revisions(&source.content) {
+            for ext in ["stdin", "stdout", "stderr"] {
+                let from = file(&format!(".{ext}"));
+                if from.exists() {
+                    let to = file(&format!(".{ORIGINAL_REVISION}.{ext}"));
+                    std::fs::rename(&from, &to).with_context(|| from.display().to_string())?;
+                    println!("moved {} to {}", from.display(), to.display());
+                }
+            }
+        }
+        for scenario in &scenarios {
+            let stdin = file(&format!(".{}.stdin", scenario.revision));
+            std::fs::write(&stdin, &scenario.stdin).with_context(|| stdin.display().to_string())?;
+            println!("wrote {}", stdin.display());
+        }
+        std::fs::write(path, add_revisions(&source.content, &scenarios))
+            .with_context(|| path.display().to_string())?;
+    }
+    ensure!(
+        !exploration.incomplete,
+        "stopped after {limit} states, there may be more to find"
+    );
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use story_rebase::{
+    args::{unknown, Args},
+    format::{diff, format},
+    span::Spanned,
+};
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("first argument must be a filename to process"))?;
+    let mut check = false;
+    for arg in Args::from_env() {
+        match arg.as_str() {
+            "--check" => check = true,
+            other => return Err(unknown(other)),
+        }
+    }
+    let source = Spanned::read_from_file(&path)?;
+    let formatted = format(source.as_ref())?;
+    if formatted == source.content {
+        return Ok(());
+    }
+    if check {
+        print!("{}", diff(&path, &source.content, &formatted));
+        bail!("{path} is not formatted");
+    }
+    std::fs::write(&path, formatted)?;
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use color_eyre::{eyre::eyre, Result};
+use story_rebase::{
+    args::{unknown, Args},
+    parsing::parse,
+    span::Spanned,
+};
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("first argument must be a filename to process"))?;
+    if let Some(arg) = Args::from_env().next() {
+        return Err(unknown(&arg));
+    }
+    let story = Spanned::read_from_file(path)?;
+    let story = parse(story.as_ref())?;
+    print!("{}", story.to_dot());
+    Ok(())
+}
@@ -0,0 +1,6 @@
+use color_eyre::Result;
+use story_rebase::lsp::serve;
+
+fn main() -> Result<()> {
+    serve(std::io::stdin().lock(), std::io::stdout().lock())
+}
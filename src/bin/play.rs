@@ -1,66 +1,218 @@
-use color_eyre::{
-    eyre::{bail, eyre},
-    Result,
+use color_eyre::{eyre::eyre, Result};
+use dialoguer::{
+    console::{Key, Term},
+    theme::{ColorfulTheme, Theme},
 };
-use dialoguer::{console::Term, theme::ColorfulTheme, Select};
-use story_rebase::{parsing::parse, span::Spanned};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use story_rebase::{
+    args::{unknown, Args},
+    parsing::parse_with_files,
+    random::Random,
+    session::Session,
+    span::Spanned,
+    story::Story,
+};
+
+/// How often the story files are checked for changes while the menu is shown.
+const POLL: Duration = Duration::from_millis(250);
+
+enum Entry {
+    /// An index into the available choices.
+    Choice(usize),
+    Undo,
+    Redo,
+    TryAgain,
+    Quit,
+}
 
 fn main() -> Result<()> {
     let path = std::env::args()
         .nth(1)
         .ok_or_else(|| eyre!("first argument must be a filename to process"))?;
-    let story = Spanned::read_from_file(path)?;
-    let mut steps = vec![];
-    'start: loop {
-        let mut story = parse(story.as_ref())?;
-        loop {
-            let room = story.room();
-            println!("{}", room.message.content);
-            if room.choices.is_empty() {
-                break;
-            }
-            loop {
-                let items: Vec<_> = room
-                    .choices
-                    .iter()
-                    .map(|choice| &choice.value.message.content)
-                    .collect();
-                let default = if let Some(choice) = steps.get(story.choices.len()) {
-                    usize::from(*choice)
-                } else {
-                    0
-                };
-                let idx = Select::with_theme(&ColorfulTheme::default())
-                    .items(&items)
-                    .default(default)
-                    .interact_on_opt(&Term::stderr())?;
-                if let Some(idx) = idx {
-                    if idx != default {
-                        steps.clear();
+    // Reparse the story whenever one of its files changed, even while the menu is shown
+    let mut watch = false;
+    for arg in Args::from_env() {
+        match arg.as_str() {
+            "--watch" => watch = true,
+            other => return Err(unknown(other)),
+        }
+    }
+    let source = Spanned::read_from_file(&path)?;
+    // Retrying keeps the seed, so the default choices lead to the same outcomes
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    let (story, files) = load(&source, seed);
+    let mut session = Session::new(story?);
+    let mut watched = contents(files);
+    let mut menu = Menu::new();
+    loop {
+        // Editors may briefly remove the file while saving, so read errors are ignored
+        let reloaded = (watch && changed(&watched))
+            .then(|| Spanned::read_from_file(&path).ok())
+            .flatten();
+        if let Some(source) = reloaded {
+            let (story, files) = load(&source, seed);
+            watched = contents(files);
+            match story {
+                Ok(new) => {
+                    println!("Reloaded {path}");
+                    if let Some(diverged) = session.reload(new) {
+                        println!("{diverged}");
                     }
-                    story.choose(idx)?;
-                    break;
                 }
+                // Keep playing the old version until the story is fixed
+                Err(err) => eprintln!("{err:?}"),
             }
         }
-        println!("Game Over! Would you like to start over? The choices you took last time will be selected by default");
-        loop {
-            let idx = Select::with_theme(&ColorfulTheme::default())
-                .items(&["Try Again", "Let me out of here!"])
-                .default(0)
-                .interact_on_opt(&Term::stderr())?;
-            match idx {
-                Some(0) => {
-                    steps = story.choices;
-                    continue 'start;
+        let story = session.story();
+        println!("{}", story.interpolate(&story.room().message));
+        let mut entries: Vec<(String, Entry)> = story
+            .available_choices()
+            .enumerate()
+            .map(|(i, choice)| (story.interpolate(&choice.message), Entry::Choice(i)))
+            .collect();
+        let ended = entries.is_empty();
+        // Selected by default, so trying again follows the last try
+        let redo = session.redo_choice();
+        if let Some(redo) = redo {
+            let message = format!("Redo \"{}\"", entries[redo].0);
+            entries.push((message, Entry::Redo));
+        }
+        if session.can_undo() {
+            entries.push(("Undo the last choice".into(), Entry::Undo));
+        }
+        if ended {
+            println!("Game Over! Would you like to start over? The choices you took last time will be selected by default");
+            entries.insert(0, ("Try Again".into(), Entry::TryAgain));
+            entries.insert(1, ("Let me out of here!".into(), Entry::Quit));
+        }
+        let labels: Vec<&str> = entries.iter().map(|(label, _)| label.as_str()).collect();
+        // Escape and changes to the story show the room again, after reloading the story
+        let Some(idx) = menu.pick(&labels, redo.unwrap_or(0), || watch && changed(&watched))?
+        else {
+            continue;
+        };
+        match entries[idx].1 {
+            Entry::Choice(idx) => session.choose(idx)?,
+            Entry::Redo => session.redo()?,
+            Entry::Undo => session.undo()?,
+            Entry::TryAgain => session.try_again(),
+            Entry::Quit => break,
+        }
+    }
+    Ok(())
+}
+
+/// A select prompt like dialoguer's, which can also be interrupted. Keys are read on
+/// another thread, as reading them blocks.
+struct Menu {
+    term: Term,
+    requests: Sender<()>,
+    keys: Receiver<std::io::Result<Key>>,
+    /// A key was requested, but not received yet.
+    pending: bool,
+}
+
+impl Menu {
+    fn new() -> Self {
+        let (requests, requested) = channel();
+        let (send, keys) = channel();
+        // Only reads when asked to, so the terminal isn't left in raw mode on exit
+        std::thread::spawn(move || {
+            let term = Term::stderr();
+            while requested.recv().is_ok() && send.send(term.read_key()).is_ok() {}
+        });
+        Self {
+            term: Term::stderr(),
+            requests,
+            keys,
+            pending: false,
+        }
+    }
+
+    /// Shows `labels` until one is picked. Returns `None` if escape was pressed or
+    /// `interrupt`, which is called every [`POLL`], returned true.
+    fn pick(
+        &mut self,
+        labels: &[&str],
+        default: usize,
+        mut interrupt: impl FnMut() -> bool,
+    ) -> Result<Option<usize>> {
+        let theme = ColorfulTheme::default();
+        let mut active = default;
+        self.term.hide_cursor()?;
+        let picked = loop {
+            for (i, label) in labels.iter().enumerate() {
+                let mut line = String::new();
+                theme.format_select_prompt_item(&mut line, label, i == active)?;
+                self.term.write_line(&line)?;
+            }
+            let key = loop {
+                if !self.pending {
+                    self.requests.send(())?;
+                    self.pending = true;
+                }
+                match self.keys.recv_timeout(POLL) {
+                    Ok(key) => {
+                        self.pending = false;
+                        break Some(key?);
+                    }
+                    Err(RecvTimeoutError::Timeout) if interrupt() => break None,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(eyre!("stopped reading keys"))
+                    }
+                }
+            };
+            self.term.clear_last_lines(labels.len())?;
+            match key {
+                None | Some(Key::Escape | Key::Char('q')) => break None,
+                Some(Key::Enter | Key::Char(' ')) => break Some(active),
+                Some(Key::ArrowDown | Key::Tab | Key::Char('j')) => {
+                    active = (active + 1) % labels.len();
                 }
-                Some(1) => break 'start,
-                Some(other) => {
-                    bail!("somehow got selection {other}, but there were only two options")
+                Some(Key::ArrowUp | Key::BackTab | Key::Char('k')) => {
+                    active = (active + labels.len() - 1) % labels.len();
                 }
-                None => {}
+                Some(_) => {}
             }
-        }
+        };
+        self.term.show_cursor()?;
+        Ok(picked)
     }
-    Ok(())
+}
+
+/// Also returns the files the story was read from, as they need to be watched even if
+/// the story doesn't parse.
+fn load(source: &Spanned<String>, seed: u64) -> (Result<Story>, Vec<PathBuf>) {
+    let (story, files) = parse_with_files(source.as_ref());
+    let story = story.map(|mut story| {
+        story.random = Random::new(seed);
+        story
+    });
+    (story, files)
+}
+
+/// The current contents of `files`, or `None` for files that can't be read.
+fn contents(files: Vec<PathBuf>) -> Vec<(PathBuf, Option<String>)> {
+    files
+        .into_iter()
+        .map(|file| {
+            let content = std::fs::read_to_string(&file).ok();
+            (file, content)
+        })
+        .collect()
+}
+
+/// Whether any of the files has different contents than when [`contents`] read them.
+/// Files that can't be read now are considered unchanged.
+fn changed(watched: &[(PathBuf, Option<String>)]) -> bool {
+    watched.iter().any(|(file, old)| {
+        std::fs::read_to_string(file).is_ok_and(|new| old.as_ref() != Some(&new))
+    })
 }
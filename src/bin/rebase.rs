@@ -0,0 +1,40 @@
+use color_eyre::{
+    eyre::{ensure, eyre},
+    Result,
+};
+use story_rebase::{
+    args::{unknown, Args},
+    parsing::parse,
+    rebase::rebase,
+    span::Spanned,
+};
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("first argument must be the new story file"))?;
+    let mut args = Args::from_env();
+    let mut old = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => old = Some(args.value("--from", "the old story file")?),
+            other => return Err(unknown(other)),
+        }
+    }
+    let old = old.ok_or_else(|| eyre!("need `--from <old story file>`"))?;
+    let old = Spanned::read_from_file(old)?;
+    let old = parse(old.as_ref())?;
+    let new = Spanned::read_from_file(path)?;
+    let new = parse(new.as_ref())?;
+
+    let choices = std::io::stdin()
+        .lines()
+        .map(|line| Ok(line?.parse()?))
+        .collect::<Result<Vec<u8>>>()?;
+    let report = rebase(&choices, old, new);
+    print!("{report}");
+    ensure!(!report.is_broken(), "could not rebase all choices");
+    let choices: Vec<_> = report.choices.iter().map(u8::to_string).collect();
+    println!("rebased choices: {}", choices.join(" "));
+    Ok(())
+}
@@ -1,23 +1,92 @@
-use color_eyre::{eyre::eyre, Result};
-use story_rebase::{parsing::parse, span::Spanned};
+use color_eyre::{
+    eyre::{bail, ensure, eyre, Context},
+    Result,
+};
+use story_rebase::{
+    args::{unknown, Args},
+    parsing::parse,
+    random::Random,
+    save::Save,
+    session::Session,
+    span::Spanned,
+    story::Story,
+};
 
 fn main() -> Result<()> {
     let path = std::env::args()
         .nth(1)
         .ok_or_else(|| eyre!("first argument must be a filename to process"))?;
-    let story = Spanned::read_from_file(path)?;
-    let mut story = parse(story.as_ref())?;
+    let mut source = Spanned::read_from_file(&path)?;
+    let mut args = Args::from_env();
+    let mut dump_save = None;
+    let mut seed = None;
+    let mut story = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dump-save" => {
+                dump_save = Some(args.value("--dump-save", "a file to write the save to")?);
+            }
+            "--seed" => {
+                seed = Some(args.value("--seed", "a number")?.parse()?);
+            }
+            "--load-save" => {
+                let save = Save::read_from_file(args.value("--load-save", "a save file")?)?;
+                let story_path = save.story.display().to_string();
+                ensure!(
+                    save.story.canonicalize().with_context(|| story_path)?
+                        == std::fs::canonicalize(&path).with_context(|| path.clone())?,
+                    "save was made for {}, not {path}",
+                    save.story.display()
+                );
+                story = Some(Story::load_save(&save)?);
+            }
+            other => return Err(unknown(other)),
+        }
+    }
+    let mut story = match story {
+        Some(story) => {
+            ensure!(seed.is_none(), "--seed cannot be combined with --load-save");
+            story
+        }
+        None => parse(source.as_ref())?,
+    };
+    if let Some(seed) = seed {
+        story.random = Random::new(seed);
+    }
+    // Trying again restarts from here, which is the save for `--load-save`
+    let mut session = Session::new(story);
     let mut lines = std::io::stdin().lines();
     loop {
-        story.print_room();
-        let idx = match lines.next() {
-            Some(line) => line?.parse()?,
-            None => break,
+        session.story().print_room();
+        let Some(line) = lines.next() else {
+            break;
         };
-        story.choose(idx)?;
+        // Choices are selected by index, target room or `> message`. Room names can't
+        // contain `:`, so the entries of `play`'s menu start with one.
+        let line = line?;
+        if let Some(file) = line.trim().strip_prefix(":reload ") {
+            // Like `play --watch` does when the story changed
+            source = Spanned::read_from_file(file.trim())?;
+            let mut new = parse(source.as_ref())?;
+            new.random = Random::new(session.story().random.seed);
+            if let Some(diverged) = session.reload(new) {
+                println!("{diverged}");
+            }
+            continue;
+        }
+        match line.trim() {
+            ":undo" => session.undo()?,
+            ":redo" => session.redo()?,
+            ":try again" => session.try_again(),
+            command if command.starts_with(':') => bail!("unknown command `{command}`"),
+            label => match label.parse() {
+                Ok(idx) => session.choose(idx)?,
+                Err(_) => session.choose_by_label(label)?,
+            },
+        }
     }
-    if std::env::args().skip(2).any(|arg| arg == "--dump-save") {
-        println!("{}", story);
+    if let Some(file) = dump_save {
+        Save::new(session.story(), source.as_ref()).write_to_file(file)?;
     }
     Ok(())
 }
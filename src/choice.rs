@@ -1,21 +1,43 @@
-use std::{fmt::Display, num::NonZeroU8};
+use std::fmt::Display;
 
 use crate::{
-    action::{Action, Operator},
-    comments::Commented,
-    span::Spanned,
-    story::Story,
+    action::Action, comments::Commented, condition::Condition, diagnostic::Diagnostic,
+    room::RoomId, span::Spanned, story::Story, variable::Value,
 };
 
-use color_eyre::Result;
+use color_eyre::{eyre::bail, Result};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Choice {
     pub message: Spanned<String>,
     /// How many times can this action be taken?
-    /// None means infinite.
-    pub repetitions: Option<Spanned<NonZeroU8>>,
-    pub action: Action,
+    /// None means infinite. Taking the choice decrements the count,
+    /// so `Some(0)` means the choice has been exhausted.
+    pub repetitions: Option<Spanned<u8>>,
+    /// The choice is hidden unless this condition holds.
+    pub condition: Option<Condition>,
+    /// Applied in order. The parser only produces `Action::Goto` or `Action::Branch`
+    /// as the last action.
+    pub actions: Vec<Action>,
+}
+
+impl Choice {
+    /// Whether there are any repetitions left for this choice
+    /// and its condition (if any) holds.
+    pub fn is_available(&self, story: &Story) -> bool {
+        !matches!(&self.repetitions, Some(n) if n.content == 0)
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.holds(story))
+    }
+
+    /// The rooms this choice may lead to. Empty if it doesn't move the player at all,
+    /// more than one for random branches.
+    pub fn targets(&self) -> impl Iterator<Item = &Spanned<RoomId>> {
+        self.actions.iter().flat_map(Action::targets)
+    }
 }
 
 impl Display for Choice {
@@ -23,31 +45,157 @@ impl Display for Choice {
         if let Some(repetitions) = &self.repetitions {
             write!(f, "{{{}}}", repetitions.content)?;
         }
-        write!(f, "{}: {}", self.action, self.message.content)
+        if let Some(condition) = &self.condition {
+            write!(f, "{condition} ")?;
+        }
+        let mut modifications = self
+            .actions
+            .iter()
+            .filter(|action| !action.moves())
+            .peekable();
+        let bracketed = modifications.peek().is_some();
+        if bracketed {
+            write!(f, "[")?;
+            for (i, action) in modifications.enumerate() {
+                if i > 0 {
+                    write!(f, "; ")?;
+                }
+                write!(f, "{action}")?;
+            }
+            write!(f, "]")?;
+        }
+        for action in &self.actions {
+            if action.moves() {
+                if bracketed {
+                    write!(f, " ")?;
+                }
+                write!(f, "{action}")?;
+            }
+        }
+        write!(f, ": {}", self.message.content)
     }
 }
 
 impl Commented<Choice> {
+    /// Applies all actions of the choice. If any of them fails,
+    /// the story is left untouched.
     pub fn apply(self, story: &mut Story) -> Result<()> {
-        match self.value.action {
-            Action::Goto(target) => story.room = target,
-            Action::Modify {
-                operator,
-                amount,
-                what,
-                room,
-            } => {
-                let value = story
-                    .rooms
-                    .get_or_insert_default(room.content)
-                    .value
-                    .items
-                    .get_or_insert_default(what.content);
-                match operator.content {
-                    Operator::Add => *value += amount.content,
+        let mut random = story.random.clone();
+        let mut changes: Vec<(RoomId, String, usize)> = vec![];
+        let mut variables: Vec<(String, Value)> = vec![];
+        let mut target = None;
+        for action in self.value.actions {
+            match action {
+                Action::Goto(room) => target = Some(room),
+                Action::Branch(branches) => {
+                    let roll = random.roll();
+                    let mut total = 0;
+                    target = branches
+                        .into_iter()
+                        .find(|branch| {
+                            total += u32::from(branch.chance.content);
+                            u32::from(roll) < total
+                        })
+                        .map(|branch| branch.room);
+                }
+                Action::Modify {
+                    operator,
+                    amount,
+                    what,
+                    room,
+                } => {
+                    let value = changes
+                        .iter()
+                        .rev()
+                        .find(|(r, w, _)| *r == room.content && *w == what.content)
+                        .map(|&(_, _, value)| value)
+                        .unwrap_or_else(|| story.item(&room.content, &what.content));
+                    let value = operator
+                        .content
+                        .apply(value, amount.content)
+                        .ok_or_else(|| {
+                            Diagnostic::error(
+                                operator.span.clone(),
+                                format!(
+                                    "cannot apply `{} {}` to `{}.{}`",
+                                    operator.content,
+                                    amount.content,
+                                    room.content.id(),
+                                    what.content,
+                                ),
+                            )
+                            .with_label(operator.content.failure(amount.content))
+                            .with_note(format!(
+                                "`{}.{}` is {value}",
+                                room.content.id(),
+                                what.content
+                            ))
+                        })?;
+                    changes.push((room.content, what.content, value));
+                }
+                Action::Set {
+                    operator,
+                    value: amount,
+                    variable,
+                } => {
+                    let value = variables
+                        .iter()
+                        .rev()
+                        .find(|(name, _)| *name == variable.content)
+                        .map(|&(_, value)| value)
+                        .or_else(|| story.variable(&variable.content))
+                        .ok_or_else(|| {
+                            Diagnostic::error(
+                                variable.span.clone(),
+                                format!("variable `{}` is not declared", variable.content),
+                            )
+                        })?;
+                    let error = |label: &str| {
+                        Diagnostic::error(
+                            operator.span.clone(),
+                            format!(
+                                "cannot apply `{} {}` to `{}`",
+                                operator.content, amount.content, variable.content,
+                            ),
+                        )
+                        .with_label(label)
+                        .with_note(format!("`{}` is {value}", variable.content))
+                    };
+                    if let Err(reason) = operator.content.check(value, amount.content) {
+                        bail!(error(&reason))
+                    }
+                    let value = match (value, amount.content) {
+                        (Value::Int(value), Value::Int(amount)) => Value::Int(
+                            operator
+                                .content
+                                .apply(value, amount)
+                                .ok_or_else(|| error(operator.content.failure(amount)))?,
+                        ),
+                        (_, amount) => amount,
+                    };
+                    variables.push((variable.content, value));
                 }
             }
         }
+        for (room, what, value) in changes {
+            *story
+                .rooms
+                .get_or_insert_default(room)
+                .value
+                .items
+                .get_or_insert_default(what) = value;
+        }
+        for (name, value) in variables {
+            let variable = story
+                .variables
+                .get_mut(&name)
+                .expect("only declared variables are changed");
+            variable.value.value.content = value;
+        }
+        if let Some(target) = target {
+            story.room = target;
+        }
+        story.random = random;
         Ok(())
     }
 }
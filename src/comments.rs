@@ -2,9 +2,10 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use std::ops::Deref;
 
-use crate::span::Spanned;
+use crate::span::{Span, Spanned};
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Comment(Spanned<String>);
 
 impl Comment {
@@ -19,9 +20,28 @@ impl Comment {
         &self.0.content
     }
 
+    /// Dummy if there is no comment. Starts after the first line's comment prefix.
+    pub fn span(&self) -> &Span {
+        &self.0.span
+    }
+
     pub fn new(text: Spanned<String>) -> Self {
         Self(text)
     }
+
+    /// Appends `other` to this comment, as if there were no lines between them.
+    pub fn join(self, other: Comment) -> Comment {
+        if self.0.content.is_empty() {
+            return other;
+        }
+        if other.0.content.is_empty() {
+            return self;
+        }
+        Self(Spanned {
+            span: self.0.span.to(&other.0.span),
+            content: format!("{}\n{}", self.0.content, other.0.content),
+        })
+    }
 }
 
 impl std::fmt::Debug for Comment {
@@ -48,6 +68,7 @@ impl Display for Comment {
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Commented<T> {
     pub comment: Comment,
     pub value: T,
@@ -0,0 +1,153 @@
+use crate::{room::RoomId, span::Spanned, story::Story, variable::Value};
+use color_eyre::eyre::bail;
+use color_eyre::Report;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A guard that must hold for a choice to be offered to the player.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Condition {
+    /// Compares the amount of an item in a room.
+    Item {
+        comparison: Spanned<Comparison>,
+        amount: Spanned<usize>,
+        what: Spanned<String>,
+        room: Spanned<RoomId>,
+    },
+    /// Compares a story-global variable.
+    Variable {
+        comparison: Spanned<Comparison>,
+        value: Spanned<Value>,
+        variable: Spanned<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Comparison {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Eq,
+    NotEq,
+}
+
+impl Comparison {
+    pub fn compare(&self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Comparison::Less => lhs < rhs,
+            Comparison::LessEq => lhs <= rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterEq => lhs >= rhs,
+            Comparison::Eq => lhs == rhs,
+            Comparison::NotEq => lhs != rhs,
+        }
+    }
+
+    /// Like `compare`, for variables.
+    /// Returns `None` if the values can't be compared, see [`Comparison::check`].
+    pub fn compare_values(&self, lhs: Value, rhs: Value) -> Option<bool> {
+        self.check(lhs, rhs).ok()?;
+        Some(match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => self.compare(lhs, rhs),
+            (lhs, rhs) => matches!(self, Comparison::Eq) == (lhs == rhs),
+        })
+    }
+
+    /// Whether a variable holding `lhs` can be compared with `rhs`.
+    /// Explains why not otherwise.
+    pub fn check(&self, lhs: Value, rhs: Value) -> Result<(), String> {
+        if !lhs.same_type(&rhs) {
+            return Err(format!("expected {}", lhs.type_description()));
+        }
+        if matches!(lhs, Value::Bool(_)) && !matches!(self, Comparison::Eq | Comparison::NotEq) {
+            return Err("booleans can only be compared with `==` and `!=`".into());
+        }
+        Ok(())
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Comparison::Less => "<",
+            Comparison::LessEq => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEq => ">=",
+            Comparison::Eq => "==",
+            Comparison::NotEq => "!=",
+        })
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessEq,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterEq,
+            "==" => Comparison::Eq,
+            "!=" => Comparison::NotEq,
+            _ => bail!("unknown comparison `{s}`"),
+        })
+    }
+}
+
+impl Condition {
+    /// Conditions on undeclared variables or with mismatched types never hold.
+    pub fn holds(&self, story: &Story) -> bool {
+        match self {
+            Condition::Item {
+                comparison,
+                amount,
+                what,
+                room,
+            } => {
+                let value = story.item(&room.content, &what.content);
+                comparison.content.compare(value, amount.content)
+            }
+            Condition::Variable {
+                comparison,
+                value,
+                variable,
+            } => story
+                .variable(&variable.content)
+                .and_then(|current| comparison.content.compare_values(current, value.content))
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Item {
+                comparison,
+                amount,
+                what,
+                room,
+            } => write!(
+                f,
+                "({}.{} {} {})",
+                room.content.id(),
+                what.content,
+                comparison.content,
+                amount.content
+            ),
+            Condition::Variable {
+                comparison,
+                value,
+                variable,
+            } => write!(
+                f,
+                "({} {} {})",
+                variable.content, comparison.content, value.content
+            ),
+        }
+    }
+}
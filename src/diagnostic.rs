@@ -0,0 +1,167 @@
+use std::fmt::Display;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// An error or warning pointing into a story file. When displayed, the
+/// lines the spans refer to are read from disk and underlined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Printed next to the carets under `span`.
+    pub label: Option<String>,
+    pub secondary: Vec<(Span, String)>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, span, message)
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, span, message)
+    }
+
+    pub fn new(severity: Severity, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            span,
+            label: None,
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.secondary.push((span, label.into()));
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Like `Display`, but prefixed with the severity, for printing
+    /// diagnostics that don't go through `color_eyre`.
+    pub fn render(&self) -> String {
+        format!("{}: {self}", self.severity)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            severity: _,
+            message,
+            span,
+            label,
+            secondary,
+            notes,
+        } = self;
+        write!(f, "{message}")?;
+        let labels = std::iter::once((span, label.as_deref().unwrap_or(""), '^'))
+            .chain(
+                secondary
+                    .iter()
+                    .map(|(span, label)| (span, &label[..], '-')),
+            )
+            .filter(|(span, _, _)| !span.is_dummy());
+        let width = std::iter::once(span)
+            .chain(secondary.iter().map(|(span, _)| span))
+            .map(|span| span.line_start().to_string().len())
+            .max()
+            .unwrap();
+        let gutter = " ".repeat(width);
+        let mut file = None;
+        for (span, label, marker) in labels {
+            if file != Some(span.file()) {
+                let arrow = if file.is_none() { "-->" } else { ":::" };
+                file = Some(span.file());
+                write!(
+                    f,
+                    "\n{gutter}{arrow} {}:{}:{}",
+                    span.file().display(),
+                    span.line_start(),
+                    span.col_start()
+                )?;
+            }
+            write!(f, "\n{gutter} |")?;
+            let line = std::fs::read_to_string(span.file())
+                .ok()
+                .and_then(|content| {
+                    content
+                        .lines()
+                        .nth(span.line_start() - 1)
+                        .map(str::to_owned)
+                })
+                .unwrap_or_default();
+            write!(f, "\n{:>width$} | {line}", span.line_start())?;
+            let end = if span.line_end() == span.line_start() {
+                span.col_end()
+            } else {
+                line.chars().count()
+            };
+            let len = (end + 1).saturating_sub(span.col_start()).max(1);
+            let padding = " ".repeat(span.col_start() - 1);
+            let markers = marker.to_string().repeat(len);
+            write!(f, "\n{gutter} | {padding}{markers}")?;
+            if !label.is_empty() {
+                write!(f, " {label}")?;
+            }
+        }
+        if !notes.is_empty() {
+            write!(f, "\n{gutter} |")?;
+        }
+        for note in notes {
+            write!(f, "\n{gutter} = note: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Several diagnostics reported at once, e.g. all errors found in a story file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl std::error::Error for Diagnostics {}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0[..] {
+            [diagnostic] => write!(f, "{diagnostic}"),
+            diagnostics => {
+                write!(f, "found {} problems", diagnostics.len())?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n\n{}", diagnostic.render())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
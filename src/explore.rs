@@ -0,0 +1,221 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Display,
+};
+
+use crate::{action::Action, room::RoomId, span::Spanned, story::Story, variable::Value};
+
+/// A choice taken during an exploration, as the index `step` reads. Random branches
+/// are explored for every outcome, so they also record the room they led to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub choice: u8,
+    pub branch: Option<RoomId>,
+}
+
+/// The choices leading from the start of the story to some state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(pub Vec<Step>);
+
+impl Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "at the start");
+        }
+        write!(f, "after")?;
+        for Step { choice, branch } in &self.0 {
+            write!(f, " {choice}")?;
+            if let Some(branch) = branch {
+                write!(f, "({})", branch.id())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fall {
+    /// The room that the choice leads to, which does not exist.
+    pub missing: RoomId,
+    pub path: Path,
+}
+
+/// What [`explore`] found. All paths are the shortest ones to their outcome.
+#[derive(Debug, Default)]
+pub struct Exploration {
+    /// How many distinct states were visited.
+    pub states: usize,
+    /// Rooms in which the player ends up without any available choices.
+    pub endings: Vec<(RoomId, Path)>,
+    /// Choices dropping the player into [`Story::default`].
+    pub falls: Vec<Fall>,
+    /// Choices that fail when taken, e.g. because an item would underflow.
+    pub failures: Vec<(Path, String)>,
+    /// Rooms that can't be reached by any sequence of choices.
+    pub unreached: Vec<RoomId>,
+    /// The state limit was hit, so there may be more of everything but `unreached`,
+    /// which may contain rooms that are reachable after all.
+    pub incomplete: bool,
+}
+
+impl Display for Exploration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "explored {} states", self.states)?;
+        if !self.endings.is_empty() {
+            writeln!(f, "endings:")?;
+        }
+        for (room, path) in &self.endings {
+            writeln!(f, "    `{}` {path}", room.id())?;
+        }
+        if !self.falls.is_empty() {
+            writeln!(f, "falls into the default room:")?;
+        }
+        for Fall { missing, path } in &self.falls {
+            writeln!(f, "    looking for `{}` {path}", missing.id())?;
+        }
+        if !self.failures.is_empty() {
+            writeln!(f, "failing choices:")?;
+        }
+        for (path, error) in &self.failures {
+            writeln!(f, "    {path}:")?;
+            for line in error.lines() {
+                writeln!(f, "        {line}")?;
+            }
+        }
+        if !self.unreached.is_empty() {
+            writeln!(f, "never reached:")?;
+        }
+        for room in &self.unreached {
+            writeln!(f, "    `{}`", room.id())?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything that decides how a story continues. Two stories in the same state
+/// offer the same choices with the same outcomes.
+#[derive(PartialEq, Eq, Hash)]
+struct State {
+    room: RoomId,
+    /// Sorted, without items that are zero.
+    items: Vec<(RoomId, String, usize)>,
+    variables: Vec<Value>,
+    repetitions: Vec<Option<u8>>,
+}
+
+impl State {
+    fn of(story: &Story) -> Self {
+        let mut items: Vec<_> = story
+            .rooms
+            .iter()
+            .flat_map(|(id, room)| {
+                room.items
+                    .iter()
+                    .filter(|(_, &amount)| amount != 0)
+                    .map(move |(item, &amount)| (id.clone(), item.clone(), amount))
+            })
+            .collect();
+        items.sort_by(|a, b| (a.0.id(), &a.1).cmp(&(b.0.id(), &b.1)));
+        Self {
+            room: story.room.content.clone(),
+            items,
+            variables: story
+                .variables
+                .values()
+                .map(|variable| variable.value.value.content)
+                .collect(),
+            repetitions: story
+                .rooms
+                .values()
+                .flat_map(|room| &room.choices)
+                .map(|choice| choice.repetitions.as_ref().map(|n| n.content))
+                .collect(),
+        }
+    }
+}
+
+/// Takes every possible sequence of choices, breadth first, until all states of the
+/// story have been visited or `limit` states were visited.
+pub fn explore(story: &Story, limit: usize) -> Exploration {
+    let mut exploration = Exploration::default();
+    let mut seen = HashSet::new();
+    let mut reached = HashSet::new();
+    let mut todo = VecDeque::from([(story.clone(), Path::default())]);
+    while let Some((story, path)) = todo.pop_front() {
+        let state = State::of(&story);
+        if seen.contains(&state) {
+            continue;
+        }
+        if seen.len() == limit {
+            exploration.incomplete = true;
+            break;
+        }
+        seen.insert(state);
+
+        let room = &story.room.content;
+        if story.rooms.get(room).is_none() {
+            if !exploration.falls.iter().any(|fall| fall.missing == *room) {
+                exploration.falls.push(Fall {
+                    missing: room.clone(),
+                    path,
+                });
+            }
+            continue;
+        }
+        reached.insert(room.clone());
+
+        let available = story.available_choices().count();
+        if available == 0 && !exploration.endings.iter().any(|(r, _)| r == room) {
+            exploration.endings.push((room.clone(), path.clone()));
+        }
+        for idx in 0..available {
+            // Saves and `step` can't select these either
+            let Ok(choice) = u8::try_from(idx) else {
+                break;
+            };
+            let taken = story.available_choices().nth(idx).expect("in range");
+            let branches: Option<Vec<Spanned<RoomId>>> =
+                taken.actions.iter().find_map(|action| match action {
+                    Action::Branch(branches) => {
+                        Some(branches.iter().map(|branch| branch.room.clone()).collect())
+                    }
+                    _ => None,
+                });
+            let mut next = story.clone();
+            let result = next.choose(idx);
+            let mut path = path.clone();
+            let step = |branch| Step { choice, branch };
+            match (result, branches) {
+                (Err(err), _) => {
+                    let error = err.to_string();
+                    if !exploration.failures.iter().any(|(_, e)| *e == error) {
+                        path.0.push(step(None));
+                        exploration.failures.push((path, error));
+                    }
+                }
+                (Ok(()), None) => {
+                    path.0.push(step(None));
+                    todo.push_back((next, path));
+                }
+                (Ok(()), Some(branches)) => {
+                    for room in branches {
+                        let mut next = next.clone();
+                        let mut path = path.clone();
+                        path.0.push(step(Some(room.content.clone())));
+                        next.room = room;
+                        todo.push_back((next, path));
+                    }
+                }
+            }
+        }
+    }
+    exploration.states = seen.len();
+    exploration.unreached = story
+        .rooms
+        .iter()
+        .map(|(id, _)| id)
+        .filter(|id| !reached.contains(*id))
+        .cloned()
+        .collect();
+    exploration
+}
@@ -0,0 +1,72 @@
+use color_eyre::Result;
+
+use crate::{parsing::parse, span::Spanned};
+
+/// Formats a story file the way `Display for Story` prints it. Whitespace around
+/// `##`, `:` and inside commands is normalized, rooms are separated by exactly one
+/// empty line, comments are moved to the element they are attached to and items
+/// are listed after the choices of their room.
+pub fn format(source: Spanned<&str>) -> Result<String> {
+    Ok(parse(source)?.to_string())
+}
+
+/// A line based diff of `old` and `new`, showing changed lines with a bit of
+/// context, in the style of `rustfmt --check`. Empty if the contents are equal.
+pub fn diff(name: &str, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 2;
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+
+    // `common[i][j]` is the length of the longest common subsequence of `old[i..]` and `new[j..]`
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    // Each line is prefixed with ` `, `-` or `+`, and remembers its line number in `old`.
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', i, old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', i, old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', i, new[j]));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|&idx| lines[idx].0 != ' ')
+        .collect();
+    let mut out = String::new();
+    let mut printed_until = 0;
+    for (n, &idx) in changed.iter().enumerate() {
+        let start = idx.saturating_sub(CONTEXT).max(printed_until);
+        if n == 0 || start > printed_until {
+            out.push_str(&format!("Diff in {name} at line {}:\n", lines[start].1 + 1));
+        }
+        let end = changed
+            .get(n + 1)
+            .map_or(lines.len(), |&next| next)
+            .min(idx + CONTEXT + 1);
+        for &(prefix, _, line) in &lines[start..end] {
+            out.push_str(&format!("{prefix}{line}"));
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        printed_until = end;
+    }
+    out
+}
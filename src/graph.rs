@@ -0,0 +1,109 @@
+use std::fmt::Write;
+
+use crate::{action::Action, room::RoomId, span::Spanned, story::Story};
+
+impl Story {
+    /// Renders the rooms and choices as a graphviz graph. Choices that modify items or
+    /// variables are blue, choices that can only be taken a limited number of times are
+    /// dashed and choices leading to rooms that don't exist are red and end in the default
+    /// room. Random branches have an edge per outcome, labelled with its chance.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot).unwrap();
+        dot
+    }
+
+    fn write_dot(&self, f: &mut String) -> std::fmt::Result {
+        writeln!(f, "digraph story {{")?;
+        writeln!(f, "    node [shape=box];")?;
+        let start = self.room.content.id();
+        for room in self.rooms.values() {
+            let id = room.id.content.id();
+            let label = escape(&format!("{id}\n{}", room.message.content));
+            write!(f, "    \"{}\" [label=\"{label}\"", escape(id))?;
+            if id == start {
+                write!(f, ", peripheries=2")?;
+            }
+            writeln!(f, "];")?;
+        }
+        let mut dangling = false;
+        for room in self.rooms.values() {
+            let from = room.id.content.id();
+            for choice in &room.choices {
+                let mut destinations: Vec<(Option<u8>, Option<&Spanned<RoomId>>)> = choice
+                    .actions
+                    .iter()
+                    .flat_map(|action| match action {
+                        Action::Branch(branches) => branches
+                            .iter()
+                            .map(|branch| (Some(branch.chance.content), Some(&branch.room)))
+                            .collect::<Vec<_>>(),
+                        action => action
+                            .targets()
+                            .map(|target| (None, Some(target)))
+                            .collect::<Vec<_>>(),
+                    })
+                    .collect();
+                if destinations.is_empty() {
+                    destinations.push((None, None));
+                }
+                for (chance, target) in destinations {
+                    let (to, missing) = match target {
+                        Some(target) if self.rooms.get(&target.content).is_some() => {
+                            (target.content.id(), None)
+                        }
+                        Some(target) => (self.default.id.content.id(), Some(target.content.id())),
+                        None => (from, None),
+                    };
+                    dangling |= missing.is_some();
+                    let mut label = choice.message.content.clone();
+                    if let Some(repetitions) = &choice.repetitions {
+                        write!(label, " {{{}}}", repetitions.content)?;
+                    }
+                    if let Some(chance) = chance {
+                        write!(label, " ({chance}%)")?;
+                    }
+                    if let Some(condition) = &choice.condition {
+                        write!(label, "\n{condition}")?;
+                    }
+                    for action in &choice.actions {
+                        if !action.moves() {
+                            write!(label, "\n[{action}]")?;
+                        }
+                    }
+                    if let Some(missing) = missing {
+                        write!(label, "\n(missing room `{missing}`)")?;
+                    }
+                    let mut attributes = vec![format!("label=\"{}\"", escape(&label))];
+                    if missing.is_some() {
+                        attributes.push("color=red".into());
+                    } else if choice.actions.iter().any(|action| !action.moves()) {
+                        attributes.push("color=blue".into());
+                    }
+                    if choice.repetitions.is_some() {
+                        attributes.push("style=dashed".into());
+                    }
+                    writeln!(
+                        f,
+                        "    \"{}\" -> \"{}\" [{}];",
+                        escape(from),
+                        escape(to),
+                        attributes.join(", ")
+                    )?;
+                }
+            }
+        }
+        if dangling {
+            let id = self.default.id.content.id();
+            let label = escape(&format!("{id}\n{}", self.default.message.content));
+            writeln!(f, "    \"{}\" [label=\"{label}\", color=red];", escape(id))?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,83 @@
+use color_eyre::{eyre::bail, Result};
+
+use crate::{diagnostic::Diagnostic, span::Spanned};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Room and item names.
+    Ident,
+    Integer,
+    /// A run of `=+-*/<>!` characters, both operators and comparisons.
+    Operator,
+    Dot,
+    Semicolon,
+    Whitespace,
+}
+
+impl TokenKind {
+    fn continues(self, c: char) -> bool {
+        match self {
+            TokenKind::Ident | TokenKind::Integer => is_ident_char(c),
+            TokenKind::Operator => is_operator_char(c),
+            TokenKind::Whitespace => c.is_whitespace(),
+            TokenKind::Dot | TokenKind::Semicolon => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: Spanned<&'a str>,
+    /// Byte offset of the token in the tokenized string.
+    pub offset: usize,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '\'')
+}
+
+fn is_operator_char(c: char) -> bool {
+    matches!(c, '=' | '+' | '-' | '*' | '/' | '<' | '>' | '!')
+}
+
+/// Splits the contents of a command (`[...]`) or condition (`(...)`) into tokens.
+pub fn tokenize(input: Spanned<&str>) -> Result<Vec<Token<'_>>> {
+    let mut tokens = vec![];
+    let mut rest = input.clone();
+    while let Some(c) = rest.content.chars().next() {
+        let kind = match c {
+            '.' => TokenKind::Dot,
+            ';' => TokenKind::Semicolon,
+            c if c.is_whitespace() => TokenKind::Whitespace,
+            c if is_operator_char(c) => TokenKind::Operator,
+            c if is_ident_char(c) => TokenKind::Ident,
+            _ => {
+                let (c, _) = rest.split_at(c.len_utf8());
+                bail!(Diagnostic::error(
+                    c.span,
+                    format!("unexpected character `{}`", c.content)
+                ))
+            }
+        };
+        let len = rest
+            .content
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| !kind.continues(c))
+            .map_or(rest.content.len(), |(i, _)| i);
+        let (text, tail) = rest.split_at(len);
+        let kind = if kind == TokenKind::Ident && text.content.chars().all(|c| c.is_ascii_digit()) {
+            TokenKind::Integer
+        } else {
+            kind
+        };
+        tokens.push(Token {
+            kind,
+            text,
+            offset: input.content.len() - rest.content.len(),
+        });
+        rest = tail;
+    }
+    Ok(tokens)
+}
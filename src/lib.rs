@@ -1,8 +1,24 @@
 pub mod action;
+pub mod args;
 pub mod choice;
 pub mod comments;
+pub mod condition;
+pub mod diagnostic;
+pub mod explore;
+pub mod format;
+pub mod graph;
+pub mod lexer;
+pub mod lsp;
 pub mod map;
+pub mod message;
 pub mod parsing;
+pub mod random;
+pub mod rebase;
+pub mod regression;
 pub mod room;
+pub mod save;
+pub mod session;
 pub mod span;
 pub mod story;
+pub mod validate;
+pub mod variable;
@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    action::Action,
+    condition::Condition,
+    diagnostic::{Diagnostic, Severity},
+    parsing::parse_recovering,
+    room::RoomId,
+    span::{Span, Spanned},
+    story::Story,
+    validate::validate,
+};
+
+/// Runs a language server for story files, speaking JSON-RPC over `input` and `output`
+/// until the client sends `exit`.
+pub fn serve(mut input: impl BufRead, output: impl Write) -> Result<()> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        included: HashMap::new(),
+    };
+    while let Some(message) = read_message(&mut input)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        server.handle(message)?;
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            len = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let len = len.ok_or_else(|| eyre!("message without `Content-Length` header"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+struct Server<W> {
+    output: W,
+    /// The text of all open documents by uri.
+    documents: HashMap<String, String>,
+    /// The uris of included files that diagnostics were last published for, by the uri
+    /// of the open document including them.
+    included: HashMap<String, Vec<String>>,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Value) -> Result<()> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn handle(&mut self, message: Value) -> Result<()> {
+        let Some(method) = message["method"].as_str() else {
+            // Responses to requests we never sent
+            return Ok(());
+        };
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let response = match self.request(method, params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32603, "message": format!("{err:#}") },
+            }),
+        };
+        self.send(response)
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_owned(), text.to_owned());
+                self.publish_diagnostics(uri)?;
+            }
+            "textDocument/didChange" => {
+                // We only ask for full document syncs, so the last change has the full text
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.to_owned(), text.to_owned());
+                }
+            }
+            "textDocument/didSave" => {
+                if let Some(text) = params["text"].as_str() {
+                    self.documents.insert(uri.to_owned(), text.to_owned());
+                }
+                self.publish_diagnostics(uri)?;
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                let included = self.included.remove(uri).unwrap_or_default();
+                for uri in [uri.to_owned()].into_iter().chain(included) {
+                    self.send(json!({
+                        "jsonrpc": "2.0",
+                        "method": "textDocument/publishDiagnostics",
+                        "params": { "uri": uri, "diagnostics": [] },
+                    }))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value> {
+        if method == "initialize" {
+            return Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": {
+                        "openClose": true,
+                        "change": 1,
+                        "save": { "includeText": true },
+                    },
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "story-rebase" },
+            }));
+        }
+        if method == "shutdown" {
+            return Ok(Value::Null);
+        }
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| eyre!("{method} without a text document"))?;
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| eyre!("{uri} is not open"))?;
+        let line = params["position"]["line"].as_u64().unwrap_or_default() as usize + 1;
+        let character = params["position"]["character"].as_u64().unwrap_or_default() as usize;
+        let col = column(text.lines().nth(line - 1).unwrap_or_default(), character);
+        let (story, _) = parse_recovering(Spanned::from_source(path(uri), text.clone()).as_ref());
+        let Some(story) = story else {
+            return Ok(Value::Null);
+        };
+        let mentions = room_mentions(&story);
+        let mention = mentions
+            .iter()
+            .find(|mention| mention.span.file() == path(uri) && contains(mention.span, line, col));
+        let header = |id: &RoomId| story.rooms.get(id).map(|room| &room.id.span);
+        Ok(match method {
+            "textDocument/definition" => mention
+                .and_then(|mention| header(mention.id))
+                .map_or(Value::Null, |span| self.location(span)),
+            "textDocument/references" => {
+                let include_declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                match mention {
+                    Some(mention) => mentions
+                        .iter()
+                        .filter(|m| m.id == mention.id)
+                        .filter(|m| include_declaration || !m.is_header)
+                        .map(|m| self.location(m.span))
+                        .collect(),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/hover" => match mention.and_then(|m| story.rooms.get(m.id)) {
+                Some(room) => json!({
+                    "contents": {
+                        "kind": "markdown",
+                        "value": format!("**{}**\n\n{}", room.id.content.id(), room.message.content),
+                    },
+                    "range": range(mention.unwrap().span, text),
+                }),
+                None => Value::Null,
+            },
+            "textDocument/completion" => {
+                let mut lines = text.lines().skip(line.saturating_sub(2));
+                let previous = if line > 1 { lines.next() } else { None };
+                let current = lines.next().unwrap_or_default();
+                let before: String = current.chars().take(col - 1).collect();
+                // Room names are only expected in the start room line and at the start of
+                // choices, which end at the first `:`. Room headers are followed by the
+                // room's message.
+                let is_message = previous.is_some_and(|previous| previous.starts_with("##"));
+                if current.starts_with("##") || is_message || before.contains(':') {
+                    json!([])
+                } else {
+                    story
+                        .rooms
+                        .values()
+                        .map(|room| {
+                            json!({
+                                "label": room.id.content.id(),
+                                "detail": room.message.content,
+                            })
+                        })
+                        .collect()
+                }
+            }
+            _ => bail!("unsupported method `{method}`"),
+        })
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<()> {
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| eyre!("{uri} is not open"))?;
+        let (story, mut diagnostics) =
+            parse_recovering(Spanned::from_source(path(uri), text.clone()).as_ref());
+        if let Some(story) = &story {
+            diagnostics.extend(validate(story));
+        }
+        // Problems in included files are published for those files
+        let mut by_uri: Vec<(String, Vec<Value>)> = vec![(uri.to_owned(), vec![])];
+        for diagnostic in &diagnostics {
+            // The client's uri of the document may be encoded differently
+            let uri = if diagnostic.span.file() == path(uri) {
+                uri.to_owned()
+            } else {
+                file_uri(&diagnostic.span)
+            };
+            let pos = match by_uri.iter().position(|(u, _)| *u == uri) {
+                Some(pos) => pos,
+                None => {
+                    by_uri.push((uri, vec![]));
+                    by_uri.len() - 1
+                }
+            };
+            by_uri[pos].1.push(self.lsp_diagnostic(diagnostic));
+        }
+        // Clear the problems of included files that were fixed since the last time
+        for stale in self.included.remove(uri).unwrap_or_default() {
+            if !by_uri.iter().any(|(u, _)| *u == stale) {
+                by_uri.push((stale, vec![]));
+            }
+        }
+        let included = by_uri[1..]
+            .iter()
+            .filter(|(_, diagnostics)| !diagnostics.is_empty())
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        self.included.insert(uri.to_owned(), included);
+        for (uri, diagnostics) in by_uri {
+            self.send(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": diagnostics },
+            }))?;
+        }
+        Ok(())
+    }
+
+    /// The text of `file`, from the open document if there is one.
+    fn text(&self, file: &Path) -> String {
+        match self.documents.iter().find(|(uri, _)| path(uri) == file) {
+            Some((_, text)) => text.clone(),
+            None => std::fs::read_to_string(file).unwrap_or_default(),
+        }
+    }
+
+    fn location(&self, span: &Span) -> Value {
+        json!({ "uri": file_uri(span), "range": range(span, &self.text(span.file())) })
+    }
+
+    fn lsp_diagnostic(&self, diagnostic: &Diagnostic) -> Value {
+        let mut message = diagnostic.message.clone();
+        if let Some(label) = &diagnostic.label {
+            message.push_str(&format!("\n{label}"));
+        }
+        for note in &diagnostic.notes {
+            message.push_str(&format!("\nnote: {note}"));
+        }
+        json!({
+            "range": range(&diagnostic.span, &self.text(diagnostic.span.file())),
+            "severity": match diagnostic.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            "source": "story-rebase",
+            "message": message,
+            "relatedInformation": diagnostic
+                .secondary
+                .iter()
+                .map(|(span, label)| json!({ "location": self.location(span), "message": label }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+struct Mention<'a> {
+    span: &'a Span,
+    id: &'a RoomId,
+    /// The `## room` line defining the room, as opposed to a reference to it.
+    is_header: bool,
+}
+
+/// Every place a room is named in the story.
+fn room_mentions(story: &Story) -> Vec<Mention<'_>> {
+    let mut mentions = vec![Mention {
+        span: &story.room.span,
+        id: &story.room.content,
+        is_header: false,
+    }];
+    for room in story.rooms.values() {
+        mentions.push(Mention {
+            span: &room.id.span,
+            id: &room.id.content,
+            is_header: true,
+        });
+        for choice in &room.choices {
+            if let Some(Condition::Item { room, .. }) = &choice.condition {
+                mentions.push(Mention {
+                    span: &room.span,
+                    id: &room.content,
+                    is_header: false,
+                });
+            }
+            for action in &choice.actions {
+                let modified = match action {
+                    Action::Modify { room, .. } => Some(room),
+                    _ => None,
+                };
+                for room in action.targets().chain(modified) {
+                    mentions.push(Mention {
+                        span: &room.span,
+                        id: &room.content,
+                        is_header: false,
+                    });
+                }
+            }
+        }
+    }
+    mentions
+}
+
+/// `line` and `col` are 1-based. The position right after the span counts too,
+/// as that's where the cursor is after typing a word.
+fn contains(span: &Span, line: usize, col: usize) -> bool {
+    (span.line_start()..=span.line_end()).contains(&line)
+        && (line > span.line_start() || col >= span.col_start())
+        && (line < span.line_end() || col <= span.col_end() + 1)
+}
+
+/// LSP positions count UTF-16 code units, while span columns count `char`s.
+/// This is the LSP character before the 1-based column `col` of `line`.
+fn character(line: &str, col: usize) -> usize {
+    line.chars()
+        .take(col.saturating_sub(1))
+        .map(char::len_utf16)
+        .sum()
+}
+
+/// The 1-based column of the `char` at the LSP character `character` of `line`.
+fn column(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    let before = line
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= character
+        })
+        .count();
+    before + 1
+}
+
+/// `text` is the content of the span's file.
+fn range(span: &Span, text: &str) -> Value {
+    let line = |n: usize| text.lines().nth(n.saturating_sub(1)).unwrap_or_default();
+    json!({
+        "start": {
+            "line": span.line_start().saturating_sub(1),
+            "character": character(line(span.line_start()), span.col_start()),
+        },
+        "end": {
+            "line": span.line_end().saturating_sub(1),
+            "character": character(line(span.line_end()), span.col_end() + 1),
+        },
+    })
+}
+
+/// Story files are parsed with the path of their uri, so `#include`s can be resolved.
+/// Uris are percent-encoded, e.g. spaces are sent as `%20`.
+fn path(uri: &str) -> PathBuf {
+    let mut rest = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut decoded = vec![];
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = match tail {
+            [high, low, ..] if byte == b'%' => char::from(*high)
+                .to_digit(16)
+                .zip(char::from(*low).to_digit(16)),
+            _ => None,
+        };
+        rest = match hex {
+            Some((high, low)) => {
+                decoded.push((high * 16 + low) as u8);
+                &tail[2..]
+            }
+            None => {
+                decoded.push(byte);
+                tail
+            }
+        };
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn file_uri(span: &Span) -> String {
+    let mut uri = String::from("file://");
+    for byte in span.file().to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(char::from(byte));
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
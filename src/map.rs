@@ -1,10 +1,11 @@
 use color_eyre::eyre::bail;
 use color_eyre::Result;
-use std::collections::hash_map::Entry;
+use std::collections::hash_map;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::hash::Hash;
 
+#[derive(Clone)]
 pub struct SortedMap<K, V> {
     entries: Vec<V>,
     entry_by_key: HashMap<K, usize>,
@@ -25,15 +26,79 @@ impl<K: Debug, V: Debug> Debug for SortedMap<K, V> {
     }
 }
 
+/// A view into a single entry of a [`SortedMap`], see [`SortedMap::entry`].
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+pub struct OccupiedEntry<'a, K, V> {
+    entry: hash_map::OccupiedEntry<'a, K, usize>,
+    entries: &'a mut Vec<V>,
+}
+
+pub struct VacantEntry<'a, K, V> {
+    entry: hash_map::VacantEntry<'a, K, usize>,
+    entries: &'a mut Vec<V>,
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        self.entry.key()
+    }
+
+    pub fn get(&self) -> &V {
+        &self.entries[*self.entry.get()]
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.entries[*self.entry.get()]
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.entries[*self.entry.get()]
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        self.entry.key()
+    }
+
+    /// Appends the value after all other entries of the map.
+    pub fn insert(self, val: V) -> &'a mut V {
+        self.entry.insert(self.entries.len());
+        self.entries.push(val);
+        self.entries.last_mut().unwrap()
+    }
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        match self {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(Default::default()),
+        }
+    }
+}
+
 impl<K: Eq + PartialEq + Hash + Debug, V: Debug> SortedMap<K, V> {
-    pub fn insert(&mut self, key: K, val: V) -> Result<()> {
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let entries = &mut self.entries;
         match self.entry_by_key.entry(key) {
-            Entry::Occupied(o) => {
-                bail!("{o:?}")
-            }
+            hash_map::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry { entry, entries }),
+            hash_map::Entry::Vacant(entry) => Entry::Vacant(VacantEntry { entry, entries }),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, val: V) -> Result<()> {
+        match self.entry(key) {
+            Entry::Occupied(o) => bail!("duplicate key {:?}", o.key()),
             Entry::Vacant(v) => {
-                v.insert(self.entries.len());
-                self.entries.push(val);
+                v.insert(val);
                 Ok(())
             }
         }
@@ -43,21 +108,16 @@ impl<K: Eq + PartialEq + Hash + Debug, V: Debug> SortedMap<K, V> {
     where
         V: Default,
     {
-        let idx = match self.entry_by_key.entry(key) {
-            Entry::Occupied(o) => *o.get(),
-            Entry::Vacant(v) => {
-                let idx = self.entries.len();
-                v.insert(idx);
-                self.entries.push(Default::default());
-                idx
-            }
-        };
-        &mut self.entries[idx]
+        self.entry(key).or_default()
     }
 
     pub fn get(&self, index: &K) -> Option<&V> {
         Some(&self.entries[*self.entry_by_key.get(index)?])
     }
+
+    pub fn get_mut(&mut self, index: &K) -> Option<&mut V> {
+        Some(&mut self.entries[*self.entry_by_key.get(index)?])
+    }
 }
 
 impl<K, V> SortedMap<K, V> {
@@ -65,6 +125,10 @@ impl<K, V> SortedMap<K, V> {
         self.entries.iter()
     }
 
+    pub fn into_values(self) -> impl Iterator<Item = V> {
+        self.entries.into_iter()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
         self.entries.iter().enumerate().map(|(i, v)| {
             (
@@ -78,3 +142,49 @@ impl<K, V> SortedMap<K, V> {
         })
     }
 }
+
+/// Serialized as a map, in insertion order.
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for SortedMap<K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> serde::Deserialize<'de> for SortedMap<K, V>
+where
+    K: serde::Deserialize<'de> + Eq + Hash + Debug,
+    V: serde::Deserialize<'de> + Debug,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<K, V>(std::marker::PhantomData<(K, V)>);
+
+        impl<'de, K, V> serde::de::Visitor<'de> for Visitor<K, V>
+        where
+            K: serde::Deserialize<'de> + Eq + Hash + Debug,
+            V: serde::Deserialize<'de> + Debug,
+        {
+            type Value = SortedMap<K, V>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a map")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut sorted = SortedMap::default();
+                while let Some((key, value)) = map.next_entry()? {
+                    sorted
+                        .insert(key, value)
+                        .map_err(serde::de::Error::custom)?;
+                }
+                Ok(sorted)
+            }
+        }
+
+        deserializer.deserialize_map(Visitor(std::marker::PhantomData))
+    }
+}
@@ -0,0 +1,36 @@
+use crate::{parsing::parse_message, room::RoomId, span::Spanned, story::Story};
+
+/// A piece of a room or choice message, see [`parse_message`].
+#[derive(Debug)]
+pub enum Segment {
+    /// Literal text, with `{{` and `}}` already unescaped.
+    Text(String),
+    /// `{room.item}`, replaced by the amount of the item.
+    Item {
+        room: Spanned<RoomId>,
+        what: Spanned<String>,
+    },
+    /// `{variable}`, replaced by the value of the variable.
+    Variable(Spanned<String>),
+}
+
+impl Story {
+    /// Replaces the placeholders in `message` with the current amounts of items
+    /// and values of variables. Placeholders of undeclared variables are kept as is.
+    pub fn interpolate(&self, message: &Spanned<String>) -> String {
+        let Ok(segments) = parse_message(message.as_ref()) else {
+            return message.content.clone();
+        };
+        segments
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text,
+                Segment::Item { room, what } => self.item(&room.content, &what.content).to_string(),
+                Segment::Variable(name) => match self.variable(&name.content) {
+                    Some(value) => value.to_string(),
+                    None => format!("{{{}}}", name.content),
+                },
+            })
+            .collect()
+    }
+}
@@ -1,67 +1,363 @@
-use color_eyre::{
-    eyre::{bail, ensure, eyre},
-    Result,
+use std::{
+    iter::Peekable,
+    path::{Path, PathBuf},
 };
 
+use color_eyre::{eyre::bail, Report, Result};
+
 use crate::{
-    action::Action,
+    action::{Action, Branch},
     choice::Choice,
-    comments::Commented,
-    room::{Room, RoomId},
-    span::Spanned,
+    comments::{Comment, Commented},
+    condition::Condition,
+    diagnostic::{Diagnostic, Diagnostics},
+    lexer::{tokenize, Token, TokenKind},
+    map::Entry,
+    message::Segment,
+    room::{Room, RoomId, DESCRIPTION_FENCE},
+    span::{Span, Spanned},
     story::Story,
+    variable::{Value, Variable},
 };
 
 pub fn parse(file_content: Spanned<&str>) -> Result<Story> {
-    let mut lines = file_content.lines("//");
-    let mut story = Story::new(lines.next().ok_or_else(|| {
-        eyre!("expected at least one line stating the starting room in the story file")
-    })?);
-    let line = lines.next().ok_or_else(|| {
-        eyre!(
-            "{}expected an empty line after the starting room",
-            file_content.span
+    parse_with_files(file_content).0
+}
+
+/// Like [`parse`], but also returns every file the story was read from, starting with
+/// `file_content`'s. Included files are listed even if they could not be read, so
+/// callers watching the files notice when they are created.
+pub fn parse_with_files(file_content: Spanned<&str>) -> (Result<Story>, Vec<PathBuf>) {
+    let (story, errors, files) = parse_all(file_content);
+    let story = match story {
+        Some(story) if errors.is_empty() => Ok(story),
+        _ => Err(Diagnostics(errors).into()),
+    };
+    (story, files)
+}
+
+/// Like [`parse`], but instead of stopping at the first error, skips the broken
+/// choice or room and keeps going. Returns all errors found and the rooms that
+/// could be parsed. There is only no story if the file is empty.
+pub fn parse_recovering(file_content: Spanned<&str>) -> (Option<Story>, Vec<Diagnostic>) {
+    let (story, errors, _) = parse_all(file_content);
+    (story, errors)
+}
+
+fn parse_all(file_content: Spanned<&str>) -> (Option<Story>, Vec<Diagnostic>, Vec<PathBuf>) {
+    let mut errors = vec![];
+    let file = file_content.span.file();
+    let mut files = vec![file.to_owned()];
+    // Stories that only exist in memory, like in the language server, can't be canonicalized
+    let mut including = vec![file.canonicalize().unwrap_or_else(|_| file.to_owned())];
+    let story = parse_file(file_content, &mut including, &mut files, &mut errors);
+    (story, errors, files)
+}
+
+/// `including` is the chain of files currently being parsed, starting with the
+/// file passed to [`parse_recovering`] and ending with `file_content`. Included files
+/// are added to `files`.
+fn parse_file(
+    file_content: Spanned<&str>,
+    including: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<Diagnostic>,
+) -> Option<Story> {
+    let mut lines = file_content.lines("//").peekable();
+    let Some(first_room) = lines.next() else {
+        errors.push(Diagnostic::error(
+            file_content.span.clone(),
+            "expected at least one line stating the starting room in the story file",
+        ));
+        return None;
+    };
+    let mut story = Story::new(first_room.map(|room| room.trim()));
+    match lines.peek() {
+        None => errors.push(Diagnostic::error(
+            file_content.span.clone(),
+            "expected an empty line after the starting room",
+        )),
+        Some(line) if !line.is_empty() => errors.push(Diagnostic::error(
+            line.span.clone(),
+            "expected an empty line after the starting room",
+        )),
+        Some(_) => {}
+    }
+
+    // Variables are declared at the top, before any room
+    let mut in_rooms = false;
+    loop {
+        // Any number of empty lines may separate rooms. Comments before empty lines
+        // belong to the next room, or to the end of the story if there is none.
+        let mut comment = Comment::default();
+        while let Some(line) = lines.next_if(|line| line.is_empty()) {
+            comment = comment.join(line.comment);
+        }
+        let Some(line) = lines.next() else {
+            story.end_comment = comment;
+            break;
+        };
+        let span = line.span.clone();
+        let line = comment.join(line.comment).with(line.value);
+        if line.starts_with("let ") {
+            if in_rooms {
+                errors.push(Diagnostic::error(
+                    span,
+                    "variables must be declared before the first room",
+                ));
+            } else if let Err(err) = parse_declaration(&mut story, line) {
+                errors.push(into_diagnostic(err, &span));
+            }
+            continue;
+        }
+        if line.starts_with("#include") {
+            if let Err(err) = parse_include(&mut story, line, including, files, errors) {
+                errors.push(into_diagnostic(err, &span));
+            }
+            continue;
+        }
+        in_rooms = true;
+        match parse_room(line, &mut lines, errors) {
+            Ok(room) => {
+                if let Err(err) = story.create_room(room) {
+                    errors.push(into_diagnostic(err, &span));
+                }
+            }
+            Err(err) => {
+                errors.push(into_diagnostic(err, &span));
+                while lines.next_if(|line| !line.starts_with("#")).is_some() {}
+            }
+        }
+    }
+    Some(story)
+}
+
+/// `let name = value` declares a story-global variable.
+fn parse_declaration(
+    story: &mut Story,
+    Commented {
+        comment,
+        value: line,
+    }: Commented<Spanned<&str>>,
+) -> Result<()> {
+    let declaration = line.strip_prefix("let").unwrap();
+    let variable = CommandParser::new(declaration)?.declaration()?;
+    story.declare_variable(comment.with(variable))
+}
+
+/// `#include "chapter.story"` parses another story file, relative to the current one,
+/// and adds its rooms and variables to `story`. The start room of the included story is ignored,
+/// so chapters can still be played on their own.
+fn parse_include(
+    story: &mut Story,
+    Commented {
+        comment,
+        value: line,
+    }: Commented<Spanned<&str>>,
+    including: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let path = line.strip_prefix("#include").unwrap().trim();
+    let Some(path) = path
+        .strip_prefix("\"")
+        .and_then(|path| path.strip_suffix("\""))
+    else {
+        bail!(Diagnostic::error(
+            path.span.clone(),
+            "expected a quoted path like `#include \"chapter.story\"`"
+        ))
+    };
+    let file = line
+        .span
+        .file()
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(path.content);
+    files.push(file.clone());
+    let content = Spanned::read_from_file(&file).map_err(|err| {
+        Diagnostic::error(
+            path.span.clone(),
+            format!("could not read `{}`", file.display()),
         )
+        .with_note(format!("{:#}", err.root_cause()))
     })?;
-    ensure!(
-        line.content.is_empty(),
-        "{}expected an empty line after the starting room",
-        line.span,
-    );
-    assert_eq!(line.comment.text(), "");
+    let canonical = file.canonicalize()?;
+    if let Some(pos) = including.iter().position(|file| *file == canonical) {
+        let chain: Vec<_> = including[pos..]
+            .iter()
+            .chain([&canonical])
+            .map(|file| format!("`{}`", file.display()))
+            .collect();
+        bail!(Diagnostic::error(path.span.clone(), "include cycle")
+            .with_label(format!("`{}` is already being included", file.display()))
+            .with_note(format!("include chain: {}", chain.join(" -> "))))
+    }
+    including.push(canonical);
+    let included = parse_file(content.as_ref(), including, files, errors);
+    including.pop();
+    story
+        .includes
+        .push(comment.with(path.map(|path| path.to_owned())));
+    let Some(included) = included else {
+        return Ok(());
+    };
+    for variable in included.variables.into_values() {
+        let span = variable.name.span.clone();
+        if let Err(err) = story.declare_variable(variable) {
+            errors.push(into_diagnostic(err, &span));
+        }
+    }
+    for room in included.rooms.into_values() {
+        let span = room.id.span.clone();
+        if let Err(err) = story.create_room(room) {
+            errors.push(into_diagnostic(err, &span));
+        }
+    }
+    Ok(())
+}
 
-    while let Some(line) = lines.next() {
-        let room = parse_room(line, &mut lines)?;
-        story.create_room(room)?;
+fn into_diagnostic(err: Report, span: &Span) -> Diagnostic {
+    err.downcast()
+        .unwrap_or_else(|err| Diagnostic::error(span.clone(), err.to_string()))
+}
+
+/// Collects the lines of a description block up to the closing fence. The lines are
+/// kept verbatim, including empty lines and lines starting with `//`.
+fn parse_description<'a>(
+    fence: Spanned<&str>,
+    lines: &mut impl Iterator<Item = Commented<Spanned<&'a str>>>,
+) -> Result<Spanned<String>> {
+    let mut content: Vec<String> = vec![];
+    let mut span: Option<Span> = None;
+    loop {
+        let Some(line) = lines.next() else {
+            bail!(
+                Diagnostic::error(fence.span, "description is never closed").with_note(format!(
+                    "descriptions end with a `{DESCRIPTION_FENCE}` line"
+                ))
+            )
+        };
+        // `lines` treats lines starting with `//` as comments, but here they are text
+        let comment = line.comment.span();
+        if !comment.is_dummy() {
+            content.extend(
+                line.comment
+                    .text()
+                    .split('\n')
+                    .map(|text| format!("//{text}")),
+            );
+            let comment = comment.from_line_start();
+            span = Some(span.map_or(comment.clone(), |span| span.to(&comment)));
+        }
+        if line.value.trim_end().content == DESCRIPTION_FENCE {
+            break;
+        }
+        content.push(line.value.content.to_owned());
+        let line = &line.value.span;
+        span = Some(span.map_or(line.clone(), |span| span.to(line)));
     }
-    Ok(story)
+    let Some(span) = span.filter(|_| content.iter().any(|line| !line.trim().is_empty())) else {
+        bail!(Diagnostic::error(fence.span, "description is empty"))
+    };
+    Ok(Spanned {
+        span,
+        content: content.join("\n"),
+    })
 }
 
+/// Choices that fail to parse are reported in `errors` and skipped.
+/// Stops before the empty line ending the room.
 fn parse_room<'a>(
     Commented {
         comment,
         value: header,
     }: Commented<Spanned<&str>>,
-    lines: &mut impl Iterator<Item = Commented<Spanned<&'a str>>>,
+    lines: &mut Peekable<impl Iterator<Item = Commented<Spanned<&'a str>>>>,
+    errors: &mut Vec<Diagnostic>,
 ) -> Result<Commented<Room>> {
     let Some(header) = header.strip_prefix("##") else {
-        bail!("{}: room header must start with ##", header.span)
+        bail!(Diagnostic::error(
+            header.span.clone(),
+            "room header must start with ##"
+        ))
     };
-    let id = header.trim_start().map(|id| RoomId::new(id));
-    let Some(message) = lines.next() else {
-        bail!("{}: trailing room header at end of file", header.span)
+    let id = header.trim().map(RoomId::new);
+    let Some(message) = lines.next_if(|line| !line.is_empty()) else {
+        bail!(Diagnostic::error(
+            header.span.clone(),
+            "room header must be followed by the room's message"
+        ))
+    };
+    let message = message.map(|message| message.trim_end());
+    let message = if message.content == DESCRIPTION_FENCE {
+        let Commented { comment, value } = message;
+        comment.with(parse_description(value, lines)?)
+    } else {
+        message.map(|message| message.map(String::from))
     };
+    if let Err(err) = parse_message(message.value.as_ref()) {
+        errors.push(into_diagnostic(err, &message.span));
+    }
     let mut room = Room::new(id, message);
-    while let Some(line) = lines.next() {
-        if line.is_empty() {
-            break;
+    while let Some(line) = lines.next_if(|line| !line.is_empty()) {
+        let span = line.span.clone();
+        if is_item(&line) {
+            if let Err(err) = parse_item(line, &mut room) {
+                errors.push(into_diagnostic(err, &span));
+            }
+            continue;
+        }
+        match parse_choice(line) {
+            Ok(choice) => room.choices.push(choice),
+            Err(err) => errors.push(into_diagnostic(err, &span)),
         }
-        room.choices.push(parse_choice(line)?);
     }
 
     Ok(comment.with(room))
 }
 
+/// Item lines (`gold = 5`) are the only lines in a room besides its message that
+/// have no `:`. Choices can't start with `=`, so this keeps choice errors intact.
+fn is_item(line: &Spanned<&str>) -> bool {
+    !line.content.contains(':')
+        && line.content.contains('=')
+        && !line.starts_with("{")
+        && !line.starts_with("(")
+        && !line.starts_with("[")
+}
+
+/// `item_line := ident '=' integer`, the starting amount of an item in the room.
+fn parse_item(
+    Commented {
+        comment,
+        value: line,
+    }: Commented<Spanned<&str>>,
+    room: &mut Room,
+) -> Result<()> {
+    if !comment.text().is_empty() {
+        bail!(
+            Diagnostic::error(line.span.clone(), "items can't have comments")
+                .with_note("move the comment before the room header or a choice")
+        )
+    }
+    let mut parser = CommandParser::new(line)?;
+    let what = parser.expect(TokenKind::Ident, "an item name")?;
+    parser.assign()?;
+    let amount = parser.amount()?;
+    parser.expect_end()?;
+    match room.items.entry(what.text.content.to_owned()) {
+        Entry::Occupied(_) => bail!(Diagnostic::error(
+            what.text.span.clone(),
+            format!("item `{}` is listed twice", what.text.content)
+        )),
+        Entry::Vacant(entry) => {
+            entry.insert(amount.content);
+            Ok(())
+        }
+    }
+}
+
 fn parse_choice(
     Commented {
         comment,
@@ -70,58 +366,383 @@ fn parse_choice(
 ) -> Result<Commented<Choice>> {
     let (repetitions, line) = if let Some(line) = line.strip_prefix("{") {
         let Some((n, line)) = line.split_once("}") else {
-            bail!("{}: repetition marker must end in `}}`", line.span);
+            bail!(Diagnostic::error(
+                line.span.clone(),
+                "repetition marker must end in `}`"
+            ));
         };
-        (Some(n.parse()?), line)
+        (Some(n.trim().parse()?), line.trim_start())
     } else {
         (None, line)
     };
-    let (action, message) = if let Some(command) = line.strip_prefix("[") {
-        let Some((command, rest)) = command.split_once("]") else {
-            bail!("{}: commands must be closed with `]`", command.span)
+    let (condition, line) = if let Some(line) = line.strip_prefix("(") {
+        let Some((condition, line)) = line.split_once(")") else {
+            bail!(Diagnostic::error(
+                line.span.clone(),
+                "conditions must be closed with `)`"
+            ));
         };
-        (parse_action(command)?, rest)
+        (
+            Some(CommandParser::new(condition)?.condition()?),
+            line.trim_start(),
+        )
     } else {
-        let Some((next, message)) = line.split_once(":") else {
-            bail!(
-                "{}: room choices must start with a room name followed by a colon",
-                line.span
-            )
+        (None, line)
+    };
+    let (mut actions, line) = if let Some(commands) = line.strip_prefix("[") {
+        let Some((commands, rest)) = commands.split_once("]") else {
+            bail!(Diagnostic::error(
+                commands.span.clone(),
+                "commands must be closed with `]`"
+            ))
         };
-        (Action::Goto(next.map(RoomId::new)), message)
+        (CommandParser::new(commands)?.actions()?, rest)
+    } else {
+        (vec![], line)
     };
+    let Some((next, message)) = line.split_once(":") else {
+        bail!(Diagnostic::error(
+            line.span.clone(),
+            "room choices must start with a room name followed by a colon"
+        ))
+    };
+    let next = next.trim();
+    if next.content.contains('|') {
+        actions.push(parse_branches(next)?);
+    } else if !next.is_empty() {
+        actions.push(Action::Goto(parse_room_name(next)?));
+    } else if actions.is_empty() {
+        bail!(Diagnostic::error(
+            next.span.clone(),
+            "room choices must have a room name or commands"
+        ))
+    }
+
+    let message = message.trim_start();
+    parse_message(message.clone())?;
 
     Ok(comment.with(Choice {
-        message: message.trim_start().map(Into::into),
+        message: message.map(Into::into),
         repetitions,
-        action,
+        condition,
+        actions,
     }))
 }
 
-fn parse_action(command: Spanned<&str>) -> Result<Action> {
-    let Some((room, rest)) = command.split_once(".") else {
-        bail!("{}: invalid room to act upon", command.span)
-    };
-    let room = room.trim();
-    let Some((what, rest)) = rest.take_while(|c| c.is_alphanumeric()) else {
-        bail!("{}: invalid room content to modify", rest.span);
-    };
-    panic!("{rest:?}");
-    let rest = rest.trim_start();
-    let Some((operator, rest)) = rest.take_while(|c| is_operator_sigil(c)) else {
-        bail!("{}: need something after operator", rest.span);
-    };
-    let operator = operator.parse()?;
-    let rest = rest.trim_start();
-    let amount = rest.parse()?;
-    Ok(Action::Modify {
-        operator,
-        amount,
-        what: what.map(Into::into),
-        room: room.map(RoomId::new),
-    })
+/// `70% hit | 30% miss`, the chances must add up to 100%.
+fn parse_branches(line: Spanned<&str>) -> Result<Action> {
+    let mut branches = vec![];
+    for branch in line.split("|") {
+        let branch = branch.trim();
+        let Some((chance, room)) = branch.split_once("%") else {
+            bail!(Diagnostic::error(
+                branch.span,
+                "random branches must start with a chance like `50%`"
+            ))
+        };
+        let room = room.trim();
+        if room.is_empty() {
+            bail!(Diagnostic::error(
+                branch.span,
+                "expected a room name after the chance"
+            ))
+        }
+        branches.push(Branch {
+            chance: chance.trim_end().parse()?,
+            room: parse_room_name(room)?,
+        });
+    }
+    let total: u32 = branches
+        .iter()
+        .map(|branch| u32::from(branch.chance.content))
+        .sum();
+    if total != 100 {
+        bail!(
+            Diagnostic::error(line.span, format!("chances add up to {total}%, not 100%"))
+                .with_label("in this random branch")
+        )
+    }
+    Ok(Action::Branch(branches))
+}
+
+/// The room a choice leads to. Markers in it are most likely misplaced, so they are
+/// rejected instead of becoming part of the name.
+fn parse_room_name(name: Spanned<&str>) -> Result<Spanned<RoomId>> {
+    if let Some(pos) = name.content.find(['[', '(', '|']) {
+        let (_, rest) = name.split_at(pos);
+        let (marker, _) = rest.split_at(1);
+        bail!(Diagnostic::error(
+            marker.span,
+            format!("unexpected `{}` in room name", marker.content)
+        )
+        .with_note("choices are written as `{n}(condition) [commands] room: message`"))
+    }
+    Ok(name.map(RoomId::new))
+}
+
+/// Splits a message into text and `{room.item}` or `{variable}` placeholders.
+/// Literal braces are written as `{{` and `}}`.
+pub fn parse_message(message: Spanned<&str>) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut text = String::new();
+    for (i, line) in message.split_lines().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        parse_message_line(line, &mut text, &mut segments)?;
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+fn parse_message_line(
+    line: Spanned<&str>,
+    text: &mut String,
+    segments: &mut Vec<Segment>,
+) -> Result<()> {
+    let mut rest = line;
+    while let Some(pos) = rest.content.find(['{', '}']) {
+        let (before, brace) = rest.split_at(pos);
+        text.push_str(before.content);
+        if let Some(after) = brace
+            .strip_prefix("{{")
+            .or_else(|| brace.strip_prefix("}}"))
+        {
+            text.push_str(&brace.content[..1]);
+            rest = after;
+            continue;
+        }
+        let (brace, after) = brace.split_at(1);
+        let note = "literal braces are written as `{{` and `}}`";
+        if brace.content == "}" {
+            bail!(Diagnostic::error(brace.span, "unmatched `}` in message").with_note(note))
+        }
+        let Some((placeholder, after)) = after.split_once("}") else {
+            bail!(Diagnostic::error(brace.span, "placeholder is never closed").with_note(note))
+        };
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(text)));
+        }
+        let mut parser = CommandParser::new(placeholder)?;
+        segments.push(match parser.place()? {
+            Place::Item(room, what) => Segment::Item { room, what },
+            Place::Variable(name) => Segment::Variable(name),
+        });
+        parser.expect_end()?;
+        rest = after;
+    }
+    text.push_str(rest.content);
+    Ok(())
+}
+
+/// Recursive descent parser for the contents of commands (`[...]`) and conditions (`(...)`).
+struct CommandParser<'a> {
+    input: Spanned<&'a str>,
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> CommandParser<'a> {
+    fn new(input: Spanned<&'a str>) -> Result<Self> {
+        let tokens = tokenize(input.clone())?;
+        Ok(Self {
+            input,
+            tokens,
+            pos: 0,
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .tokens
+            .get(self.pos)
+            .is_some_and(|token| token.kind == TokenKind::Whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos == self.tokens.len()
+    }
+
+    /// Skips whitespace and consumes the next token, which must be of the given kind.
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<Token<'a>> {
+        self.skip_whitespace();
+        match self.tokens.get(self.pos) {
+            Some(token) if token.kind == kind => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            Some(token) => bail!(Diagnostic::error(
+                token.text.span.clone(),
+                format!("expected {expected}, found `{}`", token.text.content)
+            )),
+            None => bail!(Diagnostic::error(
+                self.input.span.after(),
+                format!("expected {expected}, found end of command")
+            )),
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<()> {
+        if self.at_end() {
+            return Ok(());
+        }
+        let token = &self.tokens[self.pos];
+        bail!(Diagnostic::error(
+            token.text.span.clone(),
+            format!("unexpected `{}`", token.text.content)
+        ))
+    }
+
+    /// `room := ident (whitespace ident)*`, as room names may contain spaces.
+    fn room(&mut self) -> Result<Spanned<RoomId>> {
+        let first = self.expect(TokenKind::Ident, "a room name")?;
+        let mut last = first.clone();
+        while let [Token {
+            kind: TokenKind::Whitespace,
+            ..
+        }, next @ Token {
+            kind: TokenKind::Ident,
+            ..
+        }, ..] = &self.tokens[self.pos..]
+        {
+            last = next.clone();
+            self.pos += 2;
+        }
+        let end = last.offset + last.text.content.len();
+        Ok(Spanned {
+            span: first.text.span.to(&last.text.span),
+            content: RoomId::new(&self.input.content[first.offset..end]),
+        })
+    }
+
+    /// `place := room '.' ident | ident`, an item in a room or a story-global variable.
+    fn place(&mut self) -> Result<Place> {
+        let room = self.room()?;
+        self.skip_whitespace();
+        let dot = matches!(
+            self.tokens.get(self.pos),
+            Some(Token {
+                kind: TokenKind::Dot,
+                ..
+            })
+        );
+        // Variable names are single identifiers, room names may contain spaces
+        if !dot && !room.content.id().contains(char::is_whitespace) {
+            return Ok(Place::Variable(room.map(|id| id.id().to_owned())));
+        }
+        self.expect(TokenKind::Dot, "`.` after the room name")?;
+        let what = self.expect(TokenKind::Ident, "an item name")?;
+        Ok(Place::Item(room, what.text.map(Into::into)))
+    }
+
+    fn amount(&mut self) -> Result<Spanned<usize>> {
+        self.expect(TokenKind::Integer, "an amount")?.text.parse()
+    }
+
+    /// `value := integer | 'true' | 'false'`
+    fn value(&mut self) -> Result<Spanned<Value>> {
+        self.skip_whitespace();
+        if let Some(
+            token @ Token {
+                kind: TokenKind::Ident,
+                ..
+            },
+        ) = self.tokens.get(self.pos)
+        {
+            self.pos += 1;
+            return token.text.clone().parse();
+        }
+        self.expect(TokenKind::Integer, "a value")?.text.parse()
+    }
+
+    /// Declarations and item lines only allow plain assignment.
+    fn assign(&mut self) -> Result<()> {
+        let assign = self.expect(TokenKind::Operator, "`=`")?;
+        if assign.text.content != "=" {
+            bail!(Diagnostic::error(
+                assign.text.span.clone(),
+                format!("expected `=`, found `{}`", assign.text.content)
+            ))
+        }
+        Ok(())
+    }
+
+    /// `actions := action (';' action)*`
+    fn actions(&mut self) -> Result<Vec<Action>> {
+        let mut actions = vec![self.action()?];
+        while !self.at_end() {
+            self.expect(TokenKind::Semicolon, "`;` between commands")?;
+            actions.push(self.action()?);
+        }
+        Ok(actions)
+    }
+
+    /// `action := room '.' ident operator integer | ident operator value`
+    fn action(&mut self) -> Result<Action> {
+        let place = self.place()?;
+        let operator = self
+            .expect(TokenKind::Operator, "an operator like `+=`")?
+            .text
+            .parse()?;
+        Ok(match place {
+            Place::Item(room, what) => Action::Modify {
+                operator,
+                amount: self.amount()?,
+                what,
+                room,
+            },
+            Place::Variable(variable) => Action::Set {
+                operator,
+                value: self.value()?,
+                variable,
+            },
+        })
+    }
+
+    /// `condition := room '.' ident comparison integer | ident comparison value`
+    fn condition(&mut self) -> Result<Condition> {
+        let place = self.place()?;
+        let comparison = self
+            .expect(TokenKind::Operator, "a comparison like `>=`")?
+            .text
+            .parse()?;
+        let condition = match place {
+            Place::Item(room, what) => Condition::Item {
+                comparison,
+                amount: self.amount()?,
+                what,
+                room,
+            },
+            Place::Variable(variable) => Condition::Variable {
+                comparison,
+                value: self.value()?,
+                variable,
+            },
+        };
+        self.expect_end()?;
+        Ok(condition)
+    }
+
+    /// `declaration := ident '=' value`, after the `let`.
+    fn declaration(&mut self) -> Result<Variable> {
+        let name = self.expect(TokenKind::Ident, "a variable name")?;
+        self.assign()?;
+        let value = self.value()?;
+        self.expect_end()?;
+        Ok(Variable {
+            name: name.text.map(Into::into),
+            value,
+        })
+    }
 }
 
-fn is_operator_sigil(c: char) -> bool {
-    matches!(c, '=' | '+' | '-')
+enum Place {
+    Item(Spanned<RoomId>, Spanned<String>),
+    Variable(Spanned<String>),
 }
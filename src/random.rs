@@ -0,0 +1,43 @@
+/// The random number generator deciding weighted branches like `70% hit | 30% miss`.
+/// It is seeded explicitly and records its rolls, so a playthrough can be reproduced
+/// from its seed and choices, and checked against its rolls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Random {
+    pub seed: u64,
+    state: u64,
+    /// Every roll so far, in order.
+    pub rolls: Vec<u8>,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed,
+            rolls: vec![],
+        }
+    }
+
+    /// SplitMix64, so that rolls are stable across platforms and compiler versions.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..100`, to be compared against percentages.
+    pub fn roll(&mut self) -> u8 {
+        let roll = (self.next() % 100) as u8;
+        self.rolls.push(roll);
+        roll
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
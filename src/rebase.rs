@@ -0,0 +1,140 @@
+use std::fmt::Display;
+
+use crate::{choice::Choice, story::Story};
+
+/// What happened to a single recorded choice when moving it to the new story.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The choice is at the same index in the new story.
+    Preserved { idx: usize },
+    /// The choice moved to a different index.
+    Remapped { old: usize, new: usize },
+    /// No matching choice could be found, or taking it failed.
+    /// The rebase stops at the first broken step.
+    Broken { idx: usize, reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// The message of the choice in the old story.
+    pub message: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub steps: Vec<Step>,
+    /// The choices to take in the new story, up to the first broken step.
+    pub choices: Vec<u8>,
+}
+
+impl Report {
+    pub fn is_broken(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|step| matches!(step.outcome, Outcome::Broken { .. }))
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, Step { message, outcome }) in self.steps.iter().enumerate() {
+            match outcome {
+                Outcome::Preserved { idx } => writeln!(f, "{i}: preserved {idx} `{message}`")?,
+                Outcome::Remapped { old, new } => {
+                    writeln!(f, "{i}: remapped {old} -> {new} `{message}`")?
+                }
+                Outcome::Broken { idx, reason } => {
+                    writeln!(f, "{i}: broken {idx} `{message}`: {reason}")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replays `choices` on both stories in lockstep, looking up each choice taken in
+/// `old` by its message and target room in `new` instead of by its index.
+pub fn rebase(choices: &[u8], mut old: Story, mut new: Story) -> Report {
+    let mut report = Report::default();
+    for &idx in choices {
+        let idx = usize::from(idx);
+        let Some(choice) = old.available_choices().nth(idx) else {
+            report.steps.push(Step {
+                message: String::new(),
+                outcome: Outcome::Broken {
+                    idx,
+                    reason: format!(
+                        "the recording does not fit the old story, room `{}` has no choice {idx}",
+                        old.room.content.id()
+                    ),
+                },
+            });
+            break;
+        };
+        let message = choice.message.content.clone();
+        let Some(new_idx) = find_match(choice, idx, &new) else {
+            report.steps.push(Step {
+                message,
+                outcome: Outcome::Broken {
+                    idx,
+                    reason: format!("no matching choice in room `{}`", new.room.content.id()),
+                },
+            });
+            break;
+        };
+        let result = old.choose(idx).and_then(|()| new.choose(new_idx));
+        let outcome = match result {
+            Err(err) => Outcome::Broken {
+                idx,
+                reason: err.to_string(),
+            },
+            Ok(()) if idx == new_idx => Outcome::Preserved { idx },
+            Ok(()) => Outcome::Remapped {
+                old: idx,
+                new: new_idx,
+            },
+        };
+        let broken = matches!(outcome, Outcome::Broken { .. });
+        report.steps.push(Step { message, outcome });
+        if broken {
+            break;
+        }
+        report.choices.push(
+            new_idx
+                .try_into()
+                .expect("`Story::choose` checked the index"),
+        );
+    }
+    report
+}
+
+/// Looks for choices with the same message and target first, then for ones with just
+/// the same message, then for ones with just the same target. The first of these
+/// categories with any candidates wins, preferring `idx` if it is among them.
+fn find_match(choice: &Choice, idx: usize, new: &Story) -> Option<usize> {
+    let same_message = |c: &Choice| c.message.content == choice.message.content;
+    let targets: Vec<_> = choice.targets().map(|target| &target.content).collect();
+    let same_target = |c: &Choice| {
+        c.targets()
+            .map(|target| &target.content)
+            .eq(targets.clone())
+    };
+    let rules: [&dyn Fn(&Choice) -> bool; 3] = [
+        &|c| same_message(c) && same_target(c),
+        &same_message,
+        &|c| !targets.is_empty() && same_target(c),
+    ];
+    rules.iter().find_map(|rule| {
+        let candidates: Vec<usize> = new
+            .available_choices()
+            .enumerate()
+            .filter(|(_, c)| rule(c))
+            .map(|(i, _)| i)
+            .collect();
+        candidates
+            .contains(&idx)
+            .then_some(idx)
+            .or_else(|| candidates.first().copied())
+    })
+}
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use crate::{
+    explore::{Exploration, Path},
+    random::Random,
+    story::Story,
+};
+
+/// How many seeds are tried to make `step` follow the random branches of a path.
+const SEEDS: u64 = 1000;
+
+/// Once revisions are added to a story that had none, its existing test only keeps being
+/// run as a revision, so it becomes this one. Its `.stdin`, `.stdout` and `.stderr` files
+/// need to be renamed accordingly.
+pub const ORIGINAL_REVISION: &str = "original";
+
+/// A `step` ui test reaching one of the story's endings, as a ui_test revision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    /// The revision name, `ending_` followed by the room id. Room ids that only differ in
+    /// characters other than letters and digits get a numbered suffix, like `ending_a_b_2`.
+    pub revision: String,
+    /// The contents of the revision's `.stdin` file.
+    pub stdin: String,
+    /// `step` needs `--seed` to take the same random branches as the path.
+    pub seed: Option<u64>,
+}
+
+/// One scenario per ending found by [`crate::explore::explore`], following the shortest
+/// path to it. Endings only reachable through random branches that no seed below
+/// [`SEEDS`] leads through are returned as errors instead.
+pub fn scenarios(story: &Story, exploration: &Exploration) -> (Vec<Scenario>, Vec<String>) {
+    let mut scenarios = vec![];
+    let mut errors = vec![];
+    let mut revisions = HashSet::new();
+    for (room, path) in &exploration.endings {
+        let name: String = format!("ending_{}", room.id())
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let revision = std::iter::once(name.clone())
+            .chain((2..).map(|i| format!("{name}_{i}")))
+            .find(|revision| !revisions.contains(revision))
+            .expect("there are infinitely many suffixes");
+        revisions.insert(revision.clone());
+        let Some(seed) = (0..SEEDS).find(|&seed| follows(story, path, seed)) else {
+            errors.push(format!(
+                "no seed below {SEEDS} reaches `{}` {path}",
+                room.id()
+            ));
+            continue;
+        };
+        let stdin = path
+            .0
+            .iter()
+            .map(|step| format!("{}\n", step.choice))
+            .collect();
+        scenarios.push(Scenario {
+            revision,
+            stdin,
+            seed: (seed != 0).then_some(seed),
+        });
+    }
+    (scenarios, errors)
+}
+
+/// Whether replaying `path` with `seed` takes the same random branches as the path.
+fn follows(story: &Story, path: &Path, seed: u64) -> bool {
+    let mut story = story.clone();
+    story.random = Random::new(seed);
+    path.0.iter().all(|step| {
+        story.choose(step.choice.into()).is_ok()
+            && step
+                .branch
+                .as_ref()
+                .is_none_or(|branch| *branch == story.room.content)
+    })
+}
+
+/// Whether the `//@` header of a story's source lists revisions.
+pub fn has_revisions(source: &str) -> bool {
+    source
+        .lines()
+        .take_while(|line| line.starts_with("//@"))
+        .any(|line| line.starts_with("//@ revisions:"))
+}
+
+/// Adds the scenarios as revisions to the `//@` header of a story's source. Revisions
+/// that are already listed keep their settings, so regenerating doesn't duplicate them.
+/// A story without revisions keeps its test as the [`ORIGINAL_REVISION`].
+pub fn add_revisions(source: &str, scenarios: &[Scenario]) -> String {
+    // An empty revisions list would disable the story's test
+    if scenarios.is_empty() {
+        return source.to_owned();
+    }
+    let lines: Vec<&str> = source.lines().collect();
+    let header = lines
+        .iter()
+        .take_while(|line| line.starts_with("//@"))
+        .count();
+    let revisions_line = lines[..header]
+        .iter()
+        .position(|line| line.starts_with("//@ revisions:"));
+    let mut revisions: Vec<&str> = revisions_line.map_or(vec![ORIGINAL_REVISION], |i| {
+        lines[i]["//@ revisions:".len()..]
+            .split_whitespace()
+            .collect()
+    });
+    let check_pass = lines[..header].contains(&"//@ check-pass");
+
+    let mut settings = vec![];
+    for scenario in scenarios {
+        if revisions.contains(&scenario.revision.as_str()) {
+            continue;
+        }
+        revisions.push(&scenario.revision);
+        if !check_pass {
+            settings.push(format!("//@[{}] check-pass", scenario.revision));
+        }
+        if let Some(seed) = scenario.seed {
+            settings.push(format!(
+                "//@[{}] compile-flags: --seed {seed}",
+                scenario.revision
+            ));
+        }
+    }
+
+    let mut out = vec![format!("//@ revisions: {}", revisions.join(" "))];
+    out.extend(
+        lines[..header]
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| Some(i) != revisions_line)
+            .map(|(_, line)| line.to_string()),
+    );
+    out.extend(settings);
+    out.extend(lines[header..].iter().map(|line| line.to_string()));
+    let mut out = out.join("\n");
+    if source.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
@@ -2,7 +2,11 @@ use std::fmt::Display;
 
 use crate::{choice::Choice, comments::Commented, map::SortedMap, span::Spanned};
 
-#[derive(Debug)]
+/// Opens and closes a multi-line room description.
+pub const DESCRIPTION_FENCE: &str = "\"\"\"";
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Room {
     pub id: Spanned<RoomId>,
     pub message: Commented<Spanned<String>>,
@@ -39,7 +43,22 @@ impl Display for Room {
             items,
         } = self;
         writeln!(f, "## {}", id.content.id())?;
-        writeln!(f, "{}", message.as_ref().map(|message| &message.content))?;
+        // Messages that would not survive being parsed as a single line need a block
+        let message_line = message.content.trim_end();
+        if message_line != message.content
+            || message_line.contains('\n')
+            || message_line.starts_with("//")
+            || message_line == DESCRIPTION_FENCE
+        {
+            write!(f, "{}", message.comment)?;
+            writeln!(
+                f,
+                "{DESCRIPTION_FENCE}\n{}\n{DESCRIPTION_FENCE}",
+                message.content
+            )?;
+        } else {
+            writeln!(f, "{}", message.as_ref().map(|message| &message.content))?;
+        }
         for choice in choices {
             writeln!(f, "{choice}")?;
         }
@@ -51,6 +70,7 @@ impl Display for Room {
 }
 
 #[derive(Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RoomId(String);
 
 impl std::fmt::Debug for RoomId {
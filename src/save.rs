@@ -0,0 +1,264 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{bail, ensure, Context},
+    Result,
+};
+
+use crate::{
+    diagnostic::Diagnostic, parsing::parse, random::Random, room::RoomId, span::Spanned,
+    story::Story, variable::Value,
+};
+
+/// A save game. Instead of containing the story, it refers to the story file
+/// and records the choices taken, so it can be replayed on top of the story.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Save {
+    /// Path to the story file, relative paths are relative to the save file.
+    pub story: PathBuf,
+    /// Hash of the story file's content at the time of saving.
+    pub hash: u64,
+    /// The room the player is in.
+    pub room: RoomId,
+    pub choices: Vec<u8>,
+    /// Seed of the story's random branches. Replaying `choices` with it repeats `rolls`.
+    pub seed: u64,
+    pub rolls: Vec<u8>,
+    /// All story-global variables and their values, used to verify the replay.
+    pub variables: Vec<(String, Value)>,
+    /// All items and their amounts, used to verify the replay.
+    pub items: Vec<(RoomId, String, usize)>,
+}
+
+/// FNV-1a, so that hashes are stable across platforms and compiler versions.
+pub fn hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Display for Save {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            story,
+            hash,
+            room,
+            choices,
+            seed,
+            rolls,
+            variables,
+            items,
+        } = self;
+        writeln!(f, "story: {}", story.display())?;
+        writeln!(f, "hash: {hash:016x}")?;
+        writeln!(f, "room: {}", room.id())?;
+        write!(f, "choices:")?;
+        for choice in choices {
+            write!(f, " {choice}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "seed: {seed}")?;
+        write!(f, "rolls:")?;
+        for roll in rolls {
+            write!(f, " {roll}")?;
+        }
+        writeln!(f)?;
+        for (name, value) in variables {
+            writeln!(f, "let {name} = {value}")?;
+        }
+        for (room, item, amount) in items {
+            writeln!(f, "{}.{item} = {amount}", room.id())?;
+        }
+        Ok(())
+    }
+}
+
+impl Save {
+    pub fn new(story: &Story, source: Spanned<&str>) -> Self {
+        let items = story
+            .rooms
+            .iter()
+            .flat_map(|(id, room)| {
+                room.items
+                    .iter()
+                    .map(move |(item, &amount)| (id.clone(), item.clone(), amount))
+            })
+            .collect();
+        let variables = story
+            .variables
+            .iter()
+            .map(|(name, variable)| (name.clone(), variable.value.value.content))
+            .collect();
+        Self {
+            story: source.span.file().to_owned(),
+            hash: hash(source.content),
+            room: story.room.content.clone(),
+            choices: story.choices.clone(),
+            seed: story.random.seed,
+            rolls: story.random.rolls.clone(),
+            variables,
+            items,
+        }
+    }
+
+    /// Writes the save to `path`. The story path is made relative to the directory of
+    /// `path`, so the save keeps working when both are moved together. Stories outside of
+    /// that directory are referred to by their absolute path.
+    pub fn write_to_file(mut self, path: impl Into<PathBuf>) -> Result<()> {
+        let path = path.into();
+        let dir = std::path::absolute(&path)?
+            .parent()
+            .map(Path::to_owned)
+            .unwrap_or_default();
+        let story = std::path::absolute(&self.story)?;
+        self.story = match story.strip_prefix(&dir) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => story,
+        };
+        let path_str = path.display().to_string();
+        std::fs::write(&path, self.to_string()).with_context(|| path_str)
+    }
+
+    pub fn read_from_file(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let content = Spanned::read_from_file(&path)?;
+        let mut save = Self::parse(content.as_ref())?;
+        if let Some(dir) = path.parent() {
+            save.story = dir.join(&save.story);
+        }
+        Ok(save)
+    }
+
+    pub fn parse(content: Spanned<&str>) -> Result<Self> {
+        let mut lines = content.lines("//").map(|line| line.value).peekable();
+        let mut field = |name: &str| {
+            let line = lines.next().ok_or_else(|| {
+                Diagnostic::error(content.span.clone(), format!("save ends before `{name}:`"))
+            })?;
+            let Some(value) = line.strip_prefix(name).and_then(|l| l.strip_prefix(":")) else {
+                bail!(Diagnostic::error(
+                    line.span.clone(),
+                    format!("expected `{name}:`")
+                ))
+            };
+            Ok(value.trim())
+        };
+        let story = PathBuf::from(field("story")?.content);
+        let hash = field("hash")?;
+        let hash = u64::from_str_radix(hash.content, 16)
+            .map_err(|err| Diagnostic::error(hash.span.clone(), format!("invalid hash: {err}")))?;
+        let room = RoomId::new(field("room")?.content);
+        let numbers = |line: Spanned<&str>| {
+            line.split(" ")
+                .filter(|n| !n.is_empty())
+                .map(|n| Ok(n.parse()?.content))
+                .collect::<Result<_>>()
+        };
+        let choices = numbers(field("choices")?)?;
+        // Saves from before random branches have neither seed nor rolls
+        let mut optional = |name: &str| {
+            lines
+                .next_if(|line| line.starts_with(&format!("{name}:")))
+                .map(|line| line.split_at(name.len() + 1).1.trim())
+        };
+        let seed = match optional("seed") {
+            Some(seed) => seed.parse()?.content,
+            None => 0,
+        };
+        let rolls = match optional("rolls") {
+            Some(rolls) => numbers(rolls)?,
+            None => vec![],
+        };
+        let mut variables = vec![];
+        let mut items = vec![];
+        for line in lines {
+            let Some((name, value)) = line.split_once("=") else {
+                bail!(Diagnostic::error(
+                    line.span.clone(),
+                    "expected `let variable = value` or `room.item = amount`"
+                ))
+            };
+            if let Some(name) = name.strip_prefix("let ") {
+                let value = value.trim().parse()?.content;
+                variables.push((name.trim().content.to_owned(), value));
+                continue;
+            }
+            let Some((room, item)) = name.trim().content.rsplit_once('.') else {
+                bail!(Diagnostic::error(name.span.clone(), "expected `room.item`"))
+            };
+            let amount = value.trim().parse()?.content;
+            items.push((RoomId::new(room), item.to_owned(), amount));
+        }
+        Ok(Self {
+            story,
+            hash,
+            room,
+            choices,
+            seed,
+            rolls,
+            variables,
+            items,
+        })
+    }
+}
+
+impl Story {
+    /// Loads the story a save refers to and replays the save's choices on top of it.
+    /// Changes to the story are fine as long as the replay still ends up in the same
+    /// room with the same items, making the same random rolls on the way.
+    pub fn load_save(save: &Save) -> Result<Story> {
+        let source = Spanned::read_from_file(&save.story)?;
+        let changed = hash(&source.content) != save.hash;
+        let incompatible = |msg: String| {
+            let mut msg = format!("save is incompatible with {}: {msg}", save.story.display());
+            if changed {
+                msg.push_str(" (the story was modified after saving)");
+            }
+            msg
+        };
+        let mut story = parse(source.as_ref())?;
+        story.random = Random::new(save.seed);
+        for (i, &choice) in save.choices.iter().enumerate() {
+            story
+                .choose(choice.into())
+                .wrap_err_with(|| incompatible(format!("choice {i} can no longer be taken")))?;
+        }
+        ensure!(
+            story.room.content == save.room,
+            incompatible(format!(
+                "replay ended in room `{}` instead of `{}`",
+                story.room.content.id(),
+                save.room.id()
+            ))
+        );
+        ensure!(
+            story.random.rolls == save.rolls,
+            incompatible("replay made different random rolls".into())
+        );
+        for (name, value) in &save.variables {
+            let actual = story.variable(name);
+            ensure!(
+                actual == Some(*value),
+                incompatible(match actual {
+                    Some(actual) =>
+                        format!("replay ended with {actual} instead of {value} for `{name}`"),
+                    None => format!("variable `{name}` is no longer declared"),
+                })
+            );
+        }
+        for (room, item, amount) in &save.items {
+            let actual = story.item(room, item);
+            ensure!(
+                actual == *amount,
+                incompatible(format!(
+                    "replay ended with {actual} instead of {amount} for `{}.{item}`",
+                    room.id()
+                ))
+            );
+        }
+        Ok(story)
+    }
+}
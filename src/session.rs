@@ -0,0 +1,136 @@
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+
+use crate::{
+    rebase::{rebase, Outcome},
+    story::Story,
+};
+
+/// A playthrough of a story in which choices can be undone and redone, which can be
+/// started over, and which can move on to a new version of the story.
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// The story before the first choice, trying again restarts from here.
+    start: Story,
+    story: Story,
+    /// The story before each choice taken, undoing restores the last one.
+    history: Vec<Story>,
+    /// Choices of an earlier try or undone ones, which can be redone.
+    steps: Vec<u8>,
+}
+
+impl Session {
+    pub fn new(start: Story) -> Self {
+        Self {
+            story: start.clone(),
+            start,
+            history: vec![],
+            steps: vec![],
+        }
+    }
+
+    pub fn story(&self) -> &Story {
+        &self.story
+    }
+
+    /// Takes an available choice. Unless it is the one [`Session::redo_choice`] suggests,
+    /// this forgets the choices that could be redone.
+    pub fn choose(&mut self, idx: usize) -> Result<()> {
+        self.take(|story| story.choose(idx))
+    }
+
+    /// Like [`Session::choose`], but selects the choice like [`Story::choose_by_label`].
+    pub fn choose_by_label(&mut self, label: &str) -> Result<()> {
+        self.take(|story| story.choose_by_label(label))
+    }
+
+    fn take(&mut self, choose: impl FnOnce(&mut Story) -> Result<()>) -> Result<()> {
+        let redo = self.redo_choice();
+        let before = self.story.clone();
+        choose(&mut self.story)?;
+        let taken = self.story.choices.last().map(|&idx| usize::from(idx));
+        if taken != redo {
+            self.steps.clear();
+        }
+        self.history.push(before);
+        Ok(())
+    }
+
+    /// The available choice that was taken next in an earlier try or before undoing.
+    pub fn redo_choice(&self) -> Option<usize> {
+        self.steps
+            .get(self.story.choices.len())
+            .map(|&step| usize::from(step))
+            .filter(|&step| step < self.story.available_choices().count())
+    }
+
+    pub fn redo(&mut self) -> Result<()> {
+        let idx = self
+            .redo_choice()
+            .ok_or_else(|| eyre!("there is no choice to redo"))?;
+        self.choose(idx)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn undo(&mut self) -> Result<()> {
+        let Some(before) = self.history.pop() else {
+            bail!("there is no choice to undo");
+        };
+        self.remember();
+        self.story = before;
+        Ok(())
+    }
+
+    /// Starts over, keeping the choices taken so they can be redone.
+    pub fn try_again(&mut self) {
+        self.remember();
+        self.story = self.start.clone();
+        self.history.clear();
+    }
+
+    /// Moves on to a new version of the story by rebasing the choices taken and the ones
+    /// that could be redone onto it. If not all choices taken could be replayed, the
+    /// session continues where the replay diverged, and this describes why.
+    pub fn reload(&mut self, new: Story) -> Option<String> {
+        self.remember();
+        let report = rebase(&self.steps, self.start.clone(), new.clone());
+        let taken = self.story.choices.len();
+        let diverged =
+            report
+                .steps
+                .iter()
+                .enumerate()
+                .take(taken)
+                .find_map(|(i, step)| match &step.outcome {
+                    Outcome::Broken { reason, .. } => Some(format!(
+                        "The replay diverged at choice {i} \"{}\": {reason}",
+                        step.message
+                    )),
+                    _ => None,
+                });
+        self.steps = report.choices;
+        self.start = new;
+        self.story = self.start.clone();
+        self.history.clear();
+        for &choice in self.steps.iter().take(taken) {
+            self.history.push(self.story.clone());
+            self.story
+                .choose(choice.into())
+                .expect("the rebase took the same choice");
+        }
+        diverged
+    }
+
+    /// Records the choices taken so far as the steps to redo, unless they are already
+    /// the start of the recorded steps.
+    fn remember(&mut self) {
+        if !self.steps.starts_with(&self.story.choices) {
+            self.steps.clone_from(&self.story.choices);
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use color_eyre::{eyre::Context, Report, Result};
-use std::{fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{comments::Comment, comments::Commented};
+use crate::{comments::Comment, comments::Commented, diagnostic::Diagnostic};
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Spanned<T> {
     pub span: Span,
     pub content: T,
@@ -18,6 +23,7 @@ impl<T: std::fmt::Debug> std::fmt::Debug for Spanned<T> {
 }
 
 #[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     file: PathBuf,
     line_start: usize,
@@ -47,6 +53,60 @@ impl Span {
     pub fn is_dummy(&self) -> bool {
         self == &Self::default()
     }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// A span covering both `self` and `end` and everything in between.
+    pub fn to(&self, end: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            line_start: self.line_start,
+            col_start: self.col_start,
+            line_end: end.line_end,
+            col_end: end.col_end,
+        }
+    }
+
+    /// The span extended back to the start of its first line.
+    pub fn from_line_start(&self) -> Span {
+        Span {
+            col_start: 1,
+            ..self.clone()
+        }
+    }
+
+    /// The single character right after the span.
+    pub fn after(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            line_start: self.line_end,
+            col_start: self.col_end + 1,
+            line_end: self.line_end,
+            col_end: self.col_end + 1,
+        }
+    }
+
+    /// 1-based, inclusive.
+    pub fn line_start(&self) -> usize {
+        self.line_start
+    }
+
+    /// 1-based, inclusive.
+    pub fn line_end(&self) -> usize {
+        self.line_end
+    }
+
+    /// 1-based, inclusive, counted in `char`s.
+    pub fn col_start(&self) -> usize {
+        self.col_start
+    }
+
+    /// 1-based, inclusive, counted in `char`s.
+    pub fn col_end(&self) -> usize {
+        self.col_end
+    }
 }
 
 impl Display for Span {
@@ -68,15 +128,48 @@ impl Display for Span {
 impl Spanned<&str> {
     pub fn split_once(&self, delimiter: &str) -> Option<(Self, Self)> {
         let (a, b) = self.content.split_once(delimiter)?;
+        let delimiter_len = delimiter.chars().count();
         let mut span = self.span.clone();
-        span.col_end -= b.chars().count();
+        span.col_end -= b.chars().count() + delimiter_len;
         let a = Spanned { span, content: a };
         let mut span = self.span.clone();
-        span.col_start += a.content.chars().count() + 1;
+        span.col_start += a.content.chars().count() + delimiter_len;
         let b = Spanned { span, content: b };
         Some((a, b))
     }
 
+    /// Like `str::split`, but keeps track of the span of each piece.
+    pub fn split<'b>(&self, delimiter: &'b str) -> impl Iterator<Item = Self> + Captures<'b> {
+        let mut rest = Some(self.clone());
+        std::iter::from_fn(move || {
+            let current = rest.take()?;
+            match current.split_once(delimiter) {
+                Some((piece, tail)) => {
+                    rest = Some(tail);
+                    Some(piece)
+                }
+                None => Some(current),
+            }
+        })
+    }
+
+    /// Like `str::split('\n')`, but keeps track of the span of each line. The other
+    /// methods assume that the content is a single line.
+    pub fn split_lines(&self) -> impl Iterator<Item = Self> + '_ {
+        self.content.split('\n').enumerate().map(|(i, content)| {
+            let mut span = self.span.clone();
+            if !span.is_dummy() {
+                span.line_start += i;
+                span.line_end = span.line_start;
+                if i > 0 {
+                    span.col_start = 1;
+                }
+                span.col_end = span.col_start + content.chars().count() - 1;
+            }
+            Spanned { span, content }
+        })
+    }
+
     pub fn take_while(&self, delimiter: impl Fn(char) -> bool) -> Option<(Self, Self)> {
         let pos = self.content.find(|c| !delimiter(c))?;
         Some(self.split_at(pos))
@@ -88,7 +181,7 @@ impl Spanned<&str> {
         span.col_end -= b.chars().count();
         let a = Spanned { span, content: a };
         let mut span = self.span.clone();
-        span.col_start += a.content.chars().count() + 1;
+        span.col_start += a.content.chars().count();
         let b = Spanned { span, content: b };
         (a, b)
     }
@@ -115,6 +208,14 @@ impl Spanned<&str> {
         Some(Self { content, span })
     }
 
+    pub fn strip_suffix(&self, suffix: &str) -> Option<Self> {
+        let content = self.content.strip_suffix(suffix)?;
+        let n = self.content[content.len()..].chars().count();
+        let mut span = self.span.clone();
+        span.col_end -= n;
+        Some(Self { content, span })
+    }
+
     pub fn trim_start(&self) -> Self {
         let content = self.content.trim_start();
         let n = self.content[..(self.content.len() - content.len())]
@@ -137,11 +238,10 @@ impl Spanned<&str> {
     where
         T::Err: Into<Report>,
     {
-        let content = self
-            .content
-            .parse()
-            .map_err(Into::into)
-            .with_context(|| self.span.clone())?;
+        let content = self.content.parse().map_err(|err: T::Err| {
+            let err: Report = err.into();
+            Diagnostic::error(self.span.clone(), err.to_string())
+        })?;
         Ok(Spanned {
             span: self.span,
             content,
@@ -179,53 +279,62 @@ impl Spanned<String> {
         let path = path.into();
         let path_str = path.display().to_string();
         let story = std::fs::read_to_string(&path).with_context(|| path_str)?;
+        Ok(Self::from_source(path, story))
+    }
+
+    /// Like `read_from_file`, for file contents that are already in memory.
+    pub fn from_source(path: impl Into<PathBuf>, content: String) -> Self {
         let span = Span {
-            file: path,
+            file: path.into(),
             line_start: 1,
-            line_end: story.lines().count() + 1,
+            line_end: content.lines().count() + 1,
             col_start: 1,
             col_end: 0,
         };
-        Ok(Self {
-            span,
-            content: story,
-        })
+        Self { span, content }
     }
 }
 
 impl Spanned<&str> {
+    /// Comments are attached to the line after them. Comments at the end of the
+    /// file are attached to an empty line after the last line.
     pub fn lines<'a, 'b>(
         &'a self,
         comment_prefix: &'b str,
     ) -> impl Iterator<Item = Commented<Spanned<&'a str>>> + Captures<'b> {
         assert_eq!(self.span.col_start, 1);
-        let mut prev_comment = None;
-        self.content
-            .lines()
-            .enumerate()
-            .filter_map(move |(i, content)| {
-                let comment: Option<Spanned<String>> = prev_comment.take();
-                let mut span = self.span.clone();
-                span.line_start += i;
+        let mut prev_comment: Option<Spanned<String>> = None;
+        let mut lines = self.content.lines().enumerate();
+        std::iter::from_fn(move || loop {
+            let Some((i, content)) = lines.next() else {
+                let comment = prev_comment.take()?;
+                let mut span = comment.span.clone();
+                span.line_start = span.line_end + 1;
                 span.line_end = span.line_start;
-                span.col_end = content.chars().count();
-                let line = Spanned { content, span };
-                if let Some(new_comment) = line.strip_prefix(comment_prefix) {
-                    prev_comment = Some(if let Some(mut comment) = comment {
-                        comment.span.line_end = new_comment.span.line_end;
-                        comment.span.col_end = new_comment.span.col_end;
-                        comment.content.push('\n');
-                        comment.content.push_str(new_comment.content);
-                        comment
-                    } else {
-                        new_comment.map(Into::into)
-                    });
-
-                    None
+                span.col_start = 1;
+                span.col_end = 0;
+                return Some(Comment::new(comment).with(Spanned { content: "", span }));
+            };
+            let comment = prev_comment.take();
+            let mut span = self.span.clone();
+            span.line_start += i;
+            span.line_end = span.line_start;
+            span.col_end = content.chars().count();
+            let line = Spanned { content, span };
+            if let Some(new_comment) = line.strip_prefix(comment_prefix) {
+                prev_comment = Some(if let Some(mut comment) = comment {
+                    comment.span.line_end = new_comment.span.line_end;
+                    comment.span.col_end = new_comment.span.col_end;
+                    comment.content.push('\n');
+                    comment.content.push_str(new_comment.content);
+                    comment
                 } else {
-                    Some(comment.map(Comment::new).unwrap_or_default().with(line))
-                }
-            })
+                    new_comment.map(Into::into)
+                });
+            } else {
+                return Some(comment.map(Comment::new).unwrap_or_default().with(line));
+            }
+        })
     }
 }
 
@@ -1,21 +1,33 @@
-use color_eyre::{eyre::eyre, Result};
+use color_eyre::{eyre::bail, Result};
 
 use crate::{
     choice::Choice,
     comments::{Comment, Commented},
-    map::SortedMap,
+    diagnostic::Diagnostic,
+    map::{Entry, SortedMap},
+    random::Random,
     room::{Room, RoomId},
-    span::Spanned,
+    span::{Span, Spanned},
+    variable::{Value, Variable},
 };
 use std::ops::Index;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Story {
     pub main_comment: Comment,
     pub rooms: SortedMap<RoomId, Commented<Room>>,
     pub default: Room,
     pub room: Spanned<RoomId>,
     pub choices: Vec<u8>,
+    /// Decides random branches. Together with `choices` it makes up the history.
+    pub random: Random,
+    /// Story-global variables by name.
+    pub variables: SortedMap<String, Commented<Variable>>,
+    /// Files whose rooms were added to this story, as written in `#include "file"`.
+    pub includes: Vec<Commented<Spanned<String>>>,
+    /// Comments after the last room.
+    pub end_comment: Comment,
 }
 
 impl std::fmt::Display for Story {
@@ -26,45 +38,173 @@ impl std::fmt::Display for Story {
             default: _,
             room,
             choices: _,
+            random: _,
+            variables,
+            includes,
+            end_comment,
         } = self;
 
         writeln!(f, "{main_comment}{}", room.content.id())?;
 
-        for room in rooms.values() {
+        // Included rooms and variables are printed by their own file
+        let file = room.span.file();
+        let own = |span: &Span| span.is_dummy() || span.file() == file;
+
+        let mut variables = variables
+            .values()
+            .filter(|variable| own(&variable.name.span))
+            .peekable();
+        if variables.peek().is_some() {
+            writeln!(f)?;
+        }
+        for variable in variables {
+            writeln!(f, "{variable}")?;
+        }
+
+        if !includes.is_empty() {
+            writeln!(f)?;
+        }
+        for include in includes {
+            writeln!(f, "{}#include \"{}\"", include.comment, include.content)?;
+        }
+
+        for room in rooms.values().filter(|room| own(&room.id.span)) {
             writeln!(f)?;
             write!(f, "{}", room)?;
         }
 
+        if !end_comment.text().is_empty() {
+            write!(f, "\n{end_comment}")?;
+        }
+
         Ok(())
     }
 }
 
 impl Story {
     pub fn create_room(&mut self, room: Commented<Room>) -> Result<()> {
-        self.rooms.insert(room.id.content.clone(), room)
+        match self.rooms.entry(room.id.content.clone()) {
+            Entry::Occupied(existing) => bail!(Diagnostic::error(
+                room.id.span.clone(),
+                format!("room `{}` is defined twice", room.id.content.id())
+            )
+            .with_label("redefined here")
+            .with_secondary(existing.get().id.span.clone(), "first defined here")),
+            Entry::Vacant(entry) => {
+                entry.insert(room);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn declare_variable(&mut self, variable: Commented<Variable>) -> Result<()> {
+        match self.variables.entry(variable.name.content.clone()) {
+            Entry::Occupied(existing) => bail!(Diagnostic::error(
+                variable.name.span.clone(),
+                format!("variable `{}` is declared twice", variable.name.content)
+            )
+            .with_label("redeclared here")
+            .with_secondary(existing.get().name.span.clone(), "first declared here")),
+            Entry::Vacant(entry) => {
+                entry.insert(variable);
+                Ok(())
+            }
+        }
     }
 
     pub fn print_room(&self) {
         let room = &self[&self.room.content];
-        println!("{}", room.message.content);
-        for choice in &room.choices {
-            println!("[{}]", choice.value.message.content);
+        println!("{}", self.interpolate(&room.message));
+        for choice in self.available_choices() {
+            println!("[{}]", self.interpolate(&choice.message));
         }
     }
 
+    /// The choices of the current room that can still be taken.
+    /// Indices passed to [`Story::choose`] refer to this list.
+    pub fn available_choices(&self) -> impl Iterator<Item = &Commented<Choice>> {
+        self.room()
+            .choices
+            .iter()
+            .filter(|choice| choice.is_available(self))
+    }
+
     pub fn choose(&mut self, idx: usize) -> Result<()> {
-        self.choices.push(idx.try_into()?);
-        let choices = &self[&self.room.content].choices;
-        let choice: Commented<Choice> = choices
-            .get(idx)
+        let recorded = idx.try_into()?;
+        let available = self.available_choices().count();
+        let pos = self
+            .room()
+            .choices
+            .iter()
+            .enumerate()
+            .filter(|(_, choice)| choice.is_available(self))
+            .nth(idx)
+            .map(|(pos, _)| pos)
             .ok_or_else(|| {
-                eyre!(
-                    "chose selection {idx}, but there are only {}",
-                    choices.len()
+                Diagnostic::error(
+                    self.room().id.span.clone(),
+                    format!("chose selection {idx}, but there are only {available}"),
                 )
-            })?
-            .clone();
-        choice.apply(self)
+                .with_label(format!("this room has {available} available choices"))
+            })?;
+        let room = self.room.content.clone();
+        let choice: Commented<Choice> = self.room().choices[pos].clone();
+        choice.apply(self)?;
+        let choice = &mut self
+            .rooms
+            .get_mut(&room)
+            .expect("the default room has no choices")
+            .value
+            .choices[pos];
+        if let Some(repetitions) = &mut choice.value.repetitions {
+            repetitions.content -= 1;
+        }
+        // Only once the choice was applied, so failing choices leave the story untouched
+        self.choices.push(recorded);
+        Ok(())
+    }
+
+    /// Takes the available choice leading to the room `label`, or the one with the
+    /// message `label` if it starts with `>`, like `> go deeper`. Messages match both
+    /// with and without their placeholders filled in.
+    pub fn choose_by_label(&mut self, label: &str) -> Result<()> {
+        let label = label.trim();
+        let matches = |choice: &Choice| match label.strip_prefix('>') {
+            Some(message) => {
+                let message = message.trim();
+                choice.message.content == message || self.interpolate(&choice.message) == message
+            }
+            None => choice.targets().any(|target| target.content.id() == label),
+        };
+        let found: Vec<usize> = self
+            .available_choices()
+            .enumerate()
+            .filter(|(_, choice)| matches(choice))
+            .map(|(idx, _)| idx)
+            .collect();
+        if let [idx] = found[..] {
+            return self.choose(idx);
+        }
+        let mut diagnostic = Diagnostic::error(
+            self.room().id.span.clone(),
+            match found.len() {
+                0 => format!("no available choice matches `{label}`"),
+                n => format!("`{label}` matches {n} available choices"),
+            },
+        )
+        .with_label("in this room");
+        for (idx, choice) in self.available_choices().enumerate() {
+            let targets: Vec<_> = choice
+                .targets()
+                .map(|target| format!("`{}`", target.content.id()))
+                .collect();
+            let mut note = format!("{idx}: `> {}`", self.interpolate(&choice.message));
+            if !targets.is_empty() {
+                note.push_str(&format!(" leads to {}", targets.join(" or ")));
+            }
+            diagnostic = diagnostic.with_note(note);
+        }
+        bail!(diagnostic)
     }
 
     pub fn new(first_room: Commented<Spanned<impl Into<String>>>) -> Self {
@@ -74,12 +214,32 @@ impl Story {
             default: Default::default(),
             room: first_room.value.map(RoomId::new),
             choices: Default::default(),
+            random: Default::default(),
+            variables: Default::default(),
+            includes: Default::default(),
+            end_comment: Default::default(),
         }
     }
 
     pub fn room(&self) -> &Room {
         &self[&self.room.content]
     }
+
+    /// The amount of `what` in `room`. Items that were never touched count as zero.
+    pub fn item(&self, room: &RoomId, what: &str) -> usize {
+        self.rooms
+            .get(room)
+            .and_then(|room| room.items.get(&what.to_owned()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The current value of a story-global variable, if it was declared.
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        self.variables
+            .get(&name.to_owned())
+            .map(|variable| variable.value.value.content)
+    }
 }
 
 impl Index<&RoomId> for Story {
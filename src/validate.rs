@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+
+use crate::{
+    action::Action,
+    condition::Condition,
+    diagnostic::{Diagnostic, Severity},
+    message::Segment,
+    parsing::parse_message,
+    room::RoomId,
+    span::{Span, Spanned},
+    story::Story,
+    variable::Value,
+};
+
+/// Finds references to rooms that don't exist, rooms that can't be reached
+/// from the start room, rooms without choices and misused variables, also in
+/// message placeholders.
+pub fn validate(story: &Story) -> Vec<Diagnostic> {
+    let mut problems = vec![];
+    let problem =
+        |severity, span: &Span, message: String| Diagnostic::new(severity, span.clone(), message);
+    let exists = |id: &RoomId| story.rooms.get(id).is_some();
+
+    if !exists(&story.room.content) {
+        problems.push(problem(
+            Severity::Error,
+            &story.room.span,
+            format!("start room `{}` does not exist", story.room.content.id()),
+        ));
+    }
+
+    for room in story.rooms.values() {
+        if room.choices.is_empty() {
+            problems.push(problem(
+                Severity::Warning,
+                &room.id.span,
+                format!("room `{}` has no choices", room.id.content.id()),
+            ));
+        }
+        let messages = std::iter::once(&*room.message)
+            .chain(room.choices.iter().map(|choice| &choice.message));
+        // Messages with broken placeholders were already rejected by the parser
+        for segment in
+            messages.flat_map(|message| parse_message(message.as_ref()).unwrap_or_default())
+        {
+            match segment {
+                Segment::Item { room, .. } if !exists(&room.content) => problems.push(problem(
+                    Severity::Warning,
+                    &room.span,
+                    format!(
+                        "message shows items of room `{}`, which does not exist",
+                        room.content.id()
+                    ),
+                )),
+                Segment::Variable(name) if story.variable(&name.content).is_none() => problems
+                    .push(problem(
+                        Severity::Error,
+                        &name.span,
+                        format!("variable `{}` is not declared", name.content),
+                    )),
+                _ => {}
+            }
+        }
+        for choice in &room.choices {
+            match &choice.condition {
+                Some(Condition::Item { room, .. }) if !exists(&room.content) => {
+                    problems.push(problem(
+                        Severity::Warning,
+                        &room.span,
+                        format!(
+                            "condition checks items of room `{}`, which does not exist",
+                            room.content.id()
+                        ),
+                    ))
+                }
+                Some(Condition::Variable {
+                    comparison,
+                    value,
+                    variable,
+                }) => problems.extend(check_variable(story, variable, value, |current| {
+                    comparison
+                        .content
+                        .check(current, value.content)
+                        .map_err(|reason| {
+                            let message = format!(
+                                "cannot compare `{}` with `{} {}`",
+                                variable.content, comparison.content, value.content
+                            );
+                            (message, reason)
+                        })
+                })),
+                _ => {}
+            }
+            for action in &choice.actions {
+                match action {
+                    Action::Goto(_) | Action::Branch(_) => {
+                        for target in action.targets().filter(|t| !exists(&t.content)) {
+                            problems.push(problem(
+                                Severity::Error,
+                                &target.span,
+                                format!(
+                                    "choice leads to room `{}`, which does not exist",
+                                    target.content.id()
+                                ),
+                            ))
+                        }
+                    }
+                    Action::Modify { room, .. } if !exists(&room.content) => {
+                        problems.push(problem(
+                            Severity::Warning,
+                            &room.span,
+                            format!(
+                                "choice modifies items of room `{}`, which does not exist",
+                                room.content.id()
+                            ),
+                        ))
+                    }
+                    Action::Set {
+                        operator,
+                        value,
+                        variable,
+                    } => problems.extend(check_variable(story, variable, value, |current| {
+                        operator
+                            .content
+                            .check(current, value.content)
+                            .map_err(|reason| {
+                                let message = format!(
+                                    "cannot apply `{} {}` to `{}`",
+                                    operator.content, value.content, variable.content
+                                );
+                                (message, reason)
+                            })
+                    })),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut todo = vec![&story.room.content];
+    while let Some(id) = todo.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        let Some(room) = story.rooms.get(id) else {
+            continue;
+        };
+        todo.extend(
+            room.choices
+                .iter()
+                .flat_map(|choice| choice.targets())
+                .map(|target| &target.content),
+        );
+    }
+    for (id, room) in story.rooms.iter() {
+        if !reachable.contains(id) {
+            problems.push(problem(
+                Severity::Warning,
+                &room.id.span,
+                format!("room `{}` is unreachable from the start room", id.id()),
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Reports uses of undeclared variables and uses that don't fit the declared value.
+/// `check` gets the declared value and returns a message and the reason on mismatches.
+fn check_variable(
+    story: &Story,
+    variable: &Spanned<String>,
+    value: &Spanned<Value>,
+    check: impl FnOnce(Value) -> Result<(), (String, String)>,
+) -> Option<Diagnostic> {
+    let Some(declaration) = story.variables.get(&variable.content) else {
+        return Some(Diagnostic::error(
+            variable.span.clone(),
+            format!("variable `{}` is not declared", variable.content),
+        ));
+    };
+    let (message, reason) = check(declaration.value.value.content).err()?;
+    Some(
+        Diagnostic::error(value.span.clone(), message)
+            .with_label(reason)
+            .with_secondary(declaration.name.span.clone(), "declared here"),
+    )
+}
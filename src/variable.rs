@@ -0,0 +1,67 @@
+use color_eyre::eyre::bail;
+use color_eyre::Report;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::span::Spanned;
+
+/// A story-global variable, declared with `let name = value` after the start room.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Variable {
+    pub name: Spanned<String>,
+    /// The declared value while parsing, the current value while playing.
+    pub value: Spanned<Value>,
+}
+
+/// The type of a variable is the type of the value it is declared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Int(usize),
+    Bool(bool),
+}
+
+impl Value {
+    /// "an integer" or "a boolean", for error messages.
+    pub fn type_description(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "an integer",
+            Value::Bool(_) => "a boolean",
+        }
+    }
+
+    pub fn same_type(&self, other: &Value) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => match s.parse() {
+                Ok(n) => Value::Int(n),
+                Err(_) => bail!("expected an integer, `true` or `false`, found `{s}`"),
+            },
+        })
+    }
+}
+
+impl Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "let {} = {}", self.name.content, self.value.content)
+    }
+}
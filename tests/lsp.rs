@@ -0,0 +1,249 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use serde_json::{json, Value};
+
+const STORY: &str = "entrance
+
+## entrance
+You enter a dark cave
+out: gtfo
+corridor: go deeper
+
+## corridor
+It's dark and your steps echo far ahead of you
+entrance: return
+";
+
+const URI: &str = "file:///stories/main.story";
+
+struct Client {
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    /// Starts the language server and initializes it.
+    fn spawn() -> Result<(Child, Self)> {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut client = Client {
+            stdin: child.stdin.take().ok_or_else(|| eyre!("no stdin"))?,
+            stdout: BufReader::new(child.stdout.take().ok_or_else(|| eyre!("no stdout"))?),
+            next_id: 0,
+        };
+        let init = client.request("initialize", json!({ "capabilities": {} }))?;
+        assert_eq!(init["capabilities"]["definitionProvider"], true);
+        client.notify("initialized", json!({}))?;
+        Ok((child, client))
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Result<()> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "story", "version": 1, "text": text } }),
+        )
+    }
+
+    fn exit(mut self, mut child: Child) -> Result<()> {
+        assert_eq!(self.request("shutdown", Value::Null)?, Value::Null);
+        self.notify("exit", Value::Null)?;
+        assert!(child.wait()?.success());
+        Ok(())
+    }
+
+    fn send(&mut self, message: Value) -> Result<()> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        Ok(self.stdin.flush()?)
+    }
+
+    fn receive(&mut self) -> Result<Value> {
+        let mut len = 0;
+        loop {
+            let mut header = String::new();
+            self.stdout.read_line(&mut header)?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                len = value.trim().parse()?;
+            }
+        }
+        let mut body = vec![0; len];
+        self.stdout.read_exact(&mut body)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+        let response = self.receive()?;
+        assert_eq!(response["id"], id, "{response}");
+        Ok(response["result"].clone())
+    }
+
+    fn at(&mut self, method: &str, line: u64, character: u64) -> Result<Value> {
+        self.request(
+            method,
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true },
+            }),
+        )
+    }
+}
+
+fn range(line: u64, start: u64, end: u64) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+#[test]
+fn scripted_session() -> Result<()> {
+    let (child, mut client) = Client::spawn()?;
+    client.open(URI, STORY)?;
+    let diagnostics = client.receive()?;
+    assert_eq!(diagnostics["method"], "textDocument/publishDiagnostics");
+    let diagnostics = diagnostics["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:#?}");
+    assert_eq!(diagnostics[0]["range"], range(4, 0, 3));
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    // `corridor` in `corridor: go deeper`
+    let definition = client.at("textDocument/definition", 5, 3)?;
+    assert_eq!(definition, json!({ "uri": URI, "range": range(7, 3, 11) }));
+
+    let references = client.at("textDocument/references", 2, 5)?;
+    assert_eq!(
+        references,
+        json!([
+            { "uri": URI, "range": range(0, 0, 8) },
+            { "uri": URI, "range": range(2, 3, 11) },
+            { "uri": URI, "range": range(9, 0, 8) },
+        ])
+    );
+
+    let hover = client.at("textDocument/hover", 9, 2)?;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**entrance**\n\nYou enter a dark cave"
+    );
+
+    let completion = client.at("textDocument/completion", 10, 0)?;
+    let labels: Vec<_> = completion
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].clone())
+        .collect();
+    assert_eq!(labels, [json!("entrance"), json!("corridor")]);
+    // No completion in messages
+    assert_eq!(client.at("textDocument/completion", 9, 14)?, json!([]));
+
+    let fixed = STORY.replace("out: gtfo", "corridor: gtfo");
+    client.notify(
+        "textDocument/didSave",
+        json!({ "textDocument": { "uri": URI }, "text": fixed }),
+    )?;
+    let diagnostics = client.receive()?;
+    assert_eq!(diagnostics["params"]["diagnostics"], json!([]));
+
+    client.exit(child)
+}
+
+#[test]
+fn positions_count_utf16_code_units() -> Result<()> {
+    let (child, mut client) = Client::spawn()?;
+    client.open(
+        URI,
+        "entrance
+
+## entrance
+You enter a dark cave
+🦀 den: follow the crab
+🐚 shore: look for shells
+
+## 🦀 den
+A crab scuttles away
+entrance: go back
+",
+    )?;
+    let diagnostics = client.receive()?;
+    let diagnostics = diagnostics["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:#?}");
+    // `🐚` is two code units long
+    assert_eq!(diagnostics[0]["range"], range(5, 0, 8));
+
+    // `den` in `🦀 den: follow the crab`
+    let definition = client.at("textDocument/definition", 4, 3)?;
+    assert_eq!(definition, json!({ "uri": URI, "range": range(7, 3, 9) }));
+
+    client.exit(child)
+}
+
+#[test]
+fn fixed_problems_in_included_files_are_cleared() -> Result<()> {
+    // Clients percent-encode the paths in uris
+    let tmp = env!("CARGO_TARGET_TMPDIR");
+    let dir = std::path::Path::new(tmp).join("lsp inclüded");
+    std::fs::create_dir_all(&dir)?;
+    let chapter = dir.join("chapter.story");
+    std::fs::write(
+        &chapter,
+        "cellar\n\n## cellar\nIt smells of old wine\nnowhere: look around\n",
+    )?;
+    let main = format!("file://{tmp}/lsp%20incl%C3%BCded/main.story");
+    let chapter_uri = format!("file://{tmp}/lsp%20incl%C3%BCded/chapter.story");
+    let text = "entrance
+
+#include \"chapter.story\"
+
+## entrance
+You stand in front of an old house
+cellar: take the stairs down
+";
+
+    let (child, mut client) = Client::spawn()?;
+    client.open(&main, text)?;
+    let diagnostics = client.receive()?;
+    assert_eq!(diagnostics["params"]["uri"], main);
+    assert_eq!(diagnostics["params"]["diagnostics"], json!([]));
+    let diagnostics = client.receive()?;
+    assert_eq!(diagnostics["params"]["uri"], chapter_uri);
+    assert_eq!(
+        diagnostics["params"]["diagnostics"][0]["range"],
+        range(4, 0, 7)
+    );
+
+    std::fs::write(
+        &chapter,
+        "cellar\n\n## cellar\nIt smells of old wine\nentrance: go back up\n",
+    )?;
+    client.notify(
+        "textDocument/didSave",
+        json!({ "textDocument": { "uri": main }, "text": text }),
+    )?;
+    let diagnostics = client.receive()?;
+    assert_eq!(diagnostics["params"]["uri"], main);
+    let diagnostics = client.receive()?;
+    assert_eq!(diagnostics["params"]["uri"], chapter_uri);
+    assert_eq!(diagnostics["params"]["diagnostics"], json!([]));
+
+    client.exit(child)
+}
@@ -0,0 +1,119 @@
+use color_eyre::Result;
+use story_rebase::{
+    explore::explore,
+    parsing::parse,
+    random::Random,
+    regression::{add_revisions, scenarios, Scenario},
+    span::Spanned,
+};
+
+const STORY: &str = "//@ compile-flags: --dump-save arena.save
+arena
+
+## arena
+A goblin blocks your way
+90% hit | 10% miss: swing your sword
+flee: run away
+
+## hit
+You hit the goblin
+
+## miss
+The goblin hits you
+
+## flee
+You ran away
+";
+
+#[test]
+fn scenarios_reach_their_endings() -> Result<()> {
+    let story = parse(Spanned::from_source("arena.story", STORY.to_owned()).as_ref())?;
+    let exploration = explore(&story, 100);
+    let (scenarios, errors) = scenarios(&story, &exploration);
+    assert!(errors.is_empty(), "{errors:?}");
+    let revisions: Vec<_> = scenarios.iter().map(|s| s.revision.as_str()).collect();
+    assert_eq!(revisions, ["ending_hit", "ending_miss", "ending_flee"]);
+    for scenario in &scenarios {
+        let mut story = story.clone();
+        story.random = Random::new(scenario.seed.unwrap_or(0));
+        for line in scenario.stdin.lines() {
+            story.choose(line.parse()?)?;
+        }
+        assert_eq!(
+            format!("ending_{}", story.room.content.id()),
+            scenario.revision
+        );
+    }
+
+    // The story had no revisions, so its own test is kept as the `original` revision
+    let source = add_revisions(STORY, &scenarios);
+    let seed = scenarios[1].seed.expect("seed 0 hits the goblin");
+    let header = format!(
+        "//@ revisions: original ending_hit ending_miss ending_flee
+//@ compile-flags: --dump-save arena.save
+//@[ending_hit] check-pass
+//@[ending_miss] check-pass
+//@[ending_miss] compile-flags: --seed {seed}
+//@[ending_flee] check-pass
+arena
+"
+    );
+    assert!(source.starts_with(&header), "{source}");
+    assert!(source.ends_with(STORY.split_once('\n').unwrap().1));
+    // Regenerating keeps the header as is
+    assert_eq!(add_revisions(&source, &scenarios), source);
+    Ok(())
+}
+
+#[test]
+fn stories_without_endings_keep_their_header() -> Result<()> {
+    let source = "//@ compile-flags: --limit 5\ncounter\n";
+    assert_eq!(add_revisions(source, &[]), source);
+    Ok(())
+}
+
+#[test]
+fn stories_with_revisions_only_get_new_ones() -> Result<()> {
+    let source = "//@ revisions: go_left\n//@[go_left] check-pass\nfork\n";
+    let scenario = Scenario {
+        revision: "ending_right".into(),
+        stdin: "1\n".into(),
+        seed: None,
+    };
+    assert_eq!(
+        add_revisions(source, &[scenario]),
+        "//@ revisions: go_left ending_right
+//@[go_left] check-pass
+//@[ending_right] check-pass
+fork
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn similar_endings_get_distinct_revisions() -> Result<()> {
+    let source = "fork
+
+## fork
+Three doors
+a b: left
+a_b: middle
+a_b_2: right
+
+## a b
+The left room
+
+## a_b
+The middle room
+
+## a_b_2
+The right room
+";
+    let story = parse(Spanned::from_source("fork.story", source.to_owned()).as_ref())?;
+    let (scenarios, errors) = scenarios(&story, &explore(&story, 100));
+    assert!(errors.is_empty(), "{errors:?}");
+    let revisions: Vec<_> = scenarios.iter().map(|s| s.revision.as_str()).collect();
+    assert_eq!(revisions, ["ending_a_b", "ending_a_b_2", "ending_a_b_2_2"]);
+    Ok(())
+}
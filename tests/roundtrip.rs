@@ -4,17 +4,59 @@ use color_eyre::{
     eyre::{bail, Context},
     Result,
 };
-use story_rebase::{parsing::parse, span::Spanned};
+use story_rebase::{format::format, parsing::parse, span::Spanned};
 
 #[test]
 fn roundtrip_all() -> Result<()> {
     for file in glob::glob("**/*.story")? {
         let file = file?;
+        if file.starts_with("tests/parse_errors") || file.starts_with("tests/fmt") {
+            continue;
+        }
         roundtrip(file.clone()).context(file.display().to_string())?;
     }
     Ok(())
 }
 
+#[test]
+fn format_is_idempotent() -> Result<()> {
+    for file in glob::glob("tests/fmt/*.story")? {
+        let file = file?;
+        let source = Spanned::read_from_file(&file)?;
+        // Some of the stories are errors for the formatter
+        let Ok(formatted) = format(source.as_ref()) else {
+            continue;
+        };
+        let again = format(Spanned::from_source(&file, formatted.clone()).as_ref())?;
+        if formatted != again {
+            bail!(
+                "{}: {}",
+                file.display(),
+                pretty_assertions::StrComparison::new(&formatted, &again)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_roundtrip_all() -> Result<()> {
+    for file in glob::glob("**/*.story")? {
+        let file = file?;
+        let source = Spanned::read_from_file(&file)?;
+        // Broken stories are tested in `tests/parse_errors`
+        let Ok(story) = parse(source.as_ref()) else {
+            continue;
+        };
+        let json = serde_json::to_string(&story)?;
+        let back: story_rebase::story::Story = serde_json::from_str(&json)?;
+        assert_eq!(json, serde_json::to_string(&back)?, "{}", file.display());
+        assert_eq!(story.to_string(), back.to_string(), "{}", file.display());
+    }
+    Ok(())
+}
+
 fn roundtrip(path: PathBuf) -> Result<()> {
     let story = Spanned::read_from_file(&path)?;
     let story = parse(story.as_ref())?;
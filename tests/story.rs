@@ -0,0 +1,52 @@
+use color_eyre::Result;
+use std::path::PathBuf;
+use story_rebase::{
+    parsing::{parse, parse_with_files},
+    room::RoomId,
+    span::Spanned,
+};
+
+const STORY: &str = "bridge
+
+## bridge
+A troll blocks the bridge
+[inventory.gold += 3]: Search your pockets
+[inventory.gold -= 5] far side: Pay the toll
+
+## far side
+The troll waves you goodbye
+";
+
+#[test]
+fn failing_choices_leave_the_story_untouched() -> Result<()> {
+    let mut story = parse(Spanned::from_source("bridge.story", STORY.to_owned()).as_ref())?;
+    story.choose(0)?;
+    assert!(story.choose(1).is_err());
+    assert_eq!(story.choices, [0]);
+    assert_eq!(story.room.content.id(), "bridge");
+    assert_eq!(story.item(&RoomId::new("inventory"), "gold"), 3);
+    assert!(story.choose(2).is_err());
+    assert_eq!(story.choices, [0]);
+    Ok(())
+}
+
+#[test]
+fn all_files_of_a_story_are_listed() -> Result<()> {
+    let source = Spanned::read_from_file("tests/include/main.story")?;
+    let (story, files) = parse_with_files(source.as_ref());
+    story?;
+    assert_eq!(
+        files,
+        [
+            PathBuf::from("tests/include/main.story"),
+            PathBuf::from("tests/include/chapters/cellar.story"),
+        ]
+    );
+    // Missing files are listed too, so they can be watched until they are created
+    let source = "cellar\n\n#include \"chapters/attic.story\"\n";
+    let source = Spanned::from_source("tests/include/draft.story", source.to_owned());
+    let (story, files) = parse_with_files(source.as_ref());
+    assert!(story.is_err());
+    assert_eq!(files[1], PathBuf::from("tests/include/chapters/attic.story"));
+    Ok(())
+}
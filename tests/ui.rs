@@ -3,6 +3,7 @@ use ui_test::*;
 
 fn main() -> Result<()> {
     exec("dump", Mode::Pass)?;
+    exec("graph", Mode::Pass)?;
     exec(
         "step",
         Mode::Fail {
@@ -10,12 +11,73 @@ fn main() -> Result<()> {
             rustfix: RustfixMode::Disabled,
         },
     )?;
+    exec(
+        "check",
+        Mode::Fail {
+            require_patterns: false,
+            rustfix: RustfixMode::Disabled,
+        },
+    )?;
+    exec(
+        "rebase",
+        Mode::Fail {
+            require_patterns: false,
+            rustfix: RustfixMode::Disabled,
+        },
+    )?;
+    exec(
+        "explore",
+        Mode::Fail {
+            require_patterns: false,
+            rustfix: RustfixMode::Disabled,
+        },
+    )?;
+    // Unformatted stories, so they are excluded from the roundtrip test
+    exec(
+        "fmt",
+        Mode::Fail {
+            require_patterns: false,
+            rustfix: RustfixMode::Disabled,
+        },
+    )?;
+    exec_in(
+        "include",
+        "check",
+        Mode::Fail {
+            require_patterns: false,
+            rustfix: RustfixMode::Disabled,
+        },
+    )?;
+    exec_with_features("json", "dump", &["serde"], Mode::Pass)?;
+    // Stories that don't parse, so they are excluded from the roundtrip test
+    exec_in(
+        "parse_errors",
+        "dump",
+        Mode::Fail {
+            require_patterns: false,
+            rustfix: RustfixMode::Disabled,
+        },
+    )?;
     Ok(())
 }
 
 fn exec(name: &str, mode: Mode) -> Result<()> {
+    exec_in(name, name, mode)
+}
+
+/// Runs the binary `bin` on all stories in `tests/{dir}`.
+fn exec_in(dir: &str, bin: &str, mode: Mode) -> Result<()> {
+    exec_with_features(dir, bin, &[], mode)
+}
+
+/// Like `exec_in`, but builds `bin` with the given cargo features.
+fn exec_with_features(dir: &str, bin: &str, features: &[&str], mode: Mode) -> Result<()> {
     let mut program = CommandBuilder::cargo();
-    program.args = vec!["run".into(), "--bin".into(), name.into(), "--quiet".into()];
+    program.args = vec!["run".into(), "--bin".into(), bin.into(), "--quiet".into()];
+    for feature in features {
+        program.args.push("--features".into());
+        program.args.push(feature.into());
+    }
     program.input_file_flag = Some("--".into());
     program.out_dir_flag = None;
     let mut config = Config {
@@ -38,7 +100,7 @@ fn exec(name: &str, mode: Mode) -> Result<()> {
         skip_files: vec![],
         filter_files: vec![],
         threads: None,
-        ..Config::rustc(std::env::current_dir()?.join("tests").join(name))
+        ..Config::rustc(std::env::current_dir()?.join("tests").join(dir))
     };
 
     config.path_stdout_filter(&std::env::current_dir()?, "DIR");